@@ -8,203 +8,5450 @@
 
 use bevy::prelude::*;
 
+use bevy::ecs::system::SystemParam;
 use bevy::input::InputPlugin;
+use bevy::time::Stopwatch;
+use bevy::window::{PrimaryWindow, WindowCloseRequested, WindowFocused};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
-// Copied from bevy_dev_tools::states
-pub fn log_transitions<S: States>(mut transitions: EventReader<StateTransitionEvent<S>>) {
+use crate::persistence::save_high_score;
+
+// Adapted from bevy_dev_tools::states
+pub fn log_transitions<S: States>(
+    mut transitions: EventReader<StateTransitionEvent<S>>,
+    time: Res<Time>,
+    logs: Option<ResMut<TransitionLogs>>,
+) {
     // State internals can generate at most one event (of type) per frame.
     let Some(transition) = transitions.read().last() else {
         return;
     };
-    let name = std::any::type_name::<S>();
+    let name = short_type_name::<S>();
     let StateTransitionEvent { exited, entered } = transition;
-    info!("{} transition: {:?} => {:?}", name, exited, entered);
+    let message = format!(
+        "{} transition at {:.2}s: {} => {}",
+        name,
+        time.elapsed_secs(),
+        format_state(exited),
+        format_state(entered)
+    );
+    info!("{}", message);
+    if let Some(mut logs) = logs {
+        logs.0.push(message);
+    }
+}
+
+/// Only keep 'AppState' from 'bevy_tdd_book_use_game_state::app::AppState'
+fn short_type_name<T>() -> &'static str {
+    std::any::type_name::<T>().rsplit("::").next().unwrap_or_default()
+}
+
+/// The names of the states registered by 'add_game_logic'
+#[derive(Resource, Default)]
+pub struct RegisteredStates(pub Vec<&'static str>);
+
+fn register_state<S: States>(app: &mut App) {
+    let mut registry = app
+        .world_mut()
+        .get_resource_or_insert_with(RegisteredStates::default);
+    let name = short_type_name::<S>();
+    if !registry.0.contains(&name) {
+        registry.0.push(name);
+    }
+}
+
+/// Get the names of the states registered in the app
+pub fn registered_state_names(app: &App) -> Vec<&'static str> {
+    app.world()
+        .get_resource::<RegisteredStates>()
+        .map(|registry| registry.0.clone())
+        .unwrap_or_default()
+}
+
+fn format_state<S: States>(state: &Option<S>) -> String {
+    match state {
+        Some(state) => format!("{:?}", state),
+        None => "None".to_string(),
+    }
+}
+
+/// Insert this resource to collect the messages of 'log_transitions'
+#[derive(Resource, Default)]
+pub struct TransitionLogs(pub Vec<String>);
+
+/// Logs the keys pressed, with the state they were pressed in
+pub fn log_input(
+    input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut logs: Option<ResMut<InputLogs>>,
+) {
+    for key in input.get_just_pressed() {
+        let message = format!("[{:?}] pressed {:?}", state.get(), key);
+        info!("{}", message);
+        if let Some(logs) = logs.as_mut() {
+            logs.0.push(message);
+        }
+    }
+}
+
+/// Set to true to log the keys pressed
+#[derive(Resource, Default)]
+pub struct LogInput(pub bool);
+
+/// Insert this resource to collect the messages of 'log_input'
+#[derive(Resource, Default)]
+pub struct InputLogs(pub Vec<String>);
+
+/// Every state change, with the frame number it happened in
+#[derive(Resource, Default)]
+pub struct TransitionLog(pub Vec<(u64, Option<AppState>, Option<AppState>)>);
+
+fn record_transitions(
+    mut transitions: EventReader<StateTransitionEvent<AppState>>,
+    frame_count: Res<bevy::core::FrameCount>,
+    mut log: ResMut<TransitionLog>,
+) {
+    for transition in transitions.read() {
+        log.0.push((u64::from(frame_count.0), transition.exited, transition.entered));
+    }
 }
 
+/// Create the app without a window, starting in the menu.
+///
+/// The loading screen is skipped, so tests can start right away
 pub fn create_app() -> App {
+    create_app_in_state(AppState::Menu)
+}
+
+/// Create the app, starting in the given state instead of the menu.
+///
+/// There is no camera, as texts do not need one without a window
+pub fn create_app_in_state(state: AppState) -> App {
     let mut app = App::new();
+    app.add_plugins(MinimalPlugins);
+    app.add_plugins(InputPlugin);
+    app.add_plugins(bevy::state::app::StatesPlugin);
+    app.insert_resource(SpawnCamera(false));
+    add_game_logic(&mut app, state);
+    app
+}
+
+/// Create the app, using custom key bindings
+pub fn create_app_with_input_map(input_map: InputMap) -> App {
+    let mut app = create_app();
+    app.insert_resource(input_map);
+    app
+}
+
+/// Create the app, using custom keys to start and quit a game
+pub fn create_app_with_bindings(bindings: KeyBindings) -> App {
+    create_app_with_input_map(bindings.into())
+}
+
+/// Create the app, using custom texts
+pub fn create_app_with_strings(strings: UiStrings) -> App {
+    let mut app = create_app();
+    app.insert_resource(strings);
+    app
+}
+
+/// Create the app, using a custom number of points per second
+pub fn create_app_with_score_rate(per_second: f32) -> App {
+    let mut app = create_app();
+    app.insert_resource(ScoreRate::new(per_second));
+    app
+}
+
+/// Create the app, playing at the given difficulty
+pub fn create_app_with_difficulty(difficulty: Difficulty) -> App {
+    let mut app = create_app();
+    app.insert_resource(difficulty);
+    app
+}
+
+/// Create the app, where the game is won at the given score
+pub fn create_app_with_win_target(target: u32) -> App {
+    let mut app = create_app();
+    app.insert_resource(WinTarget(target));
+    app
+}
+
+/// Create the app, using a custom seed for the random numbers in a game
+pub fn create_app_with_seed(seed: u64) -> App {
+    let mut app = create_app();
+    app.insert_resource(GameSeed(seed));
+    app
+}
+
+/// Create the app, showing the texts in the given language
+pub fn create_app_with_language(language: Language) -> App {
+    let mut app = create_app();
+    app.insert_resource(language);
+    app
+}
+
+/// Create the app, fading to black and back when changing states
+pub fn create_app_with_fade(seconds: f32) -> App {
+    let mut app = create_app();
+    app.insert_resource(TransitionTimer::new(seconds));
+    app
+}
+
+/// Create the app, counting down before a game starts
+pub fn create_app_with_countdown(seconds: f32) -> App {
+    let mut app = create_app();
+    app.insert_resource(CountdownTimer::new(seconds));
+    app
+}
+
+/// Create the app, where each game ends after the given number of seconds
+pub fn create_app_timed(seconds: f32) -> App {
+    let mut app = create_app();
+    app.insert_resource(GameDuration::new(seconds));
+    app
+}
+
+/// Create the app without a camera, for when the embedding app has one
+pub fn create_app_without_camera() -> App {
+    let mut app = create_app();
+    app.insert_resource(SpawnCamera(false));
+    app
+}
+
+/// Create the app, going back to the menu when there is no input
+/// for the given number of seconds in a game
+pub fn create_app_with_idle_timeout(seconds: f32) -> App {
+    let mut app = create_app();
+    app.insert_resource(IdleTimer::new(seconds));
+    app
+}
+
+/// Create the app, blinking the menu text every given number of seconds
+pub fn create_app_with_blinking_prompt(seconds: f32) -> App {
+    let mut app = create_app();
+    app.insert_resource(BlinkTimer::new(seconds));
+    app
+}
 
-    // The function 'try_add_plugins' 
+/// Create the app, responding to the keyboard in the menu at a fixed rate
+pub fn create_app_with_fixed_input() -> App {
+    let mut app = create_app();
+    app.insert_resource(FixedInput);
+    app
+}
+
+/// Create the app, ignoring keyboard input for the given number of seconds
+/// after each state change
+pub fn create_app_with_transition_cooldown(seconds: f32) -> App {
+    let mut app = create_app();
+    app.insert_resource(TransitionCooldown::new(seconds));
+    app
+}
+
+/// Create the app with a window, as used by 'main'.
+///
+/// Unit and integration tests use 'create_app' instead,
+/// as a window cannot be created outside of the main thread.
+pub fn create_windowed_app() -> App {
+    let mut app = App::new();
+    // The function 'try_add_plugins'
     // (https://github.com/bevyengine/bevy/discussions/15802#discussioncomment-10898148)
-    // will make this if obsolete and increase code coverage.
+    // will make the split between this function and 'create_app_in_state' obsolete.
     // Thanks mgi388 for pointing this out
-    if cfg!(test) {
-        app.add_plugins(MinimalPlugins);
-        app.add_plugins(InputPlugin);
+    // Closing the window is handled by 'respond_to_window_close'
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        close_when_requested: false,
+        ..default()
+    }));
+    add_game_logic(&mut app, AppState::default());
+    app.insert_resource(TransitionTimer::new(0.5));
+    app.insert_resource(AutoPauseOnBlur(true));
+    app.insert_resource(CountdownTimer::new(3.0));
+    app.insert_resource(IdleTimer::new(30.0));
+    app.insert_resource(BlinkTimer::new(0.5));
+    app.insert_resource(TransitionCooldown::new(0.15));
+    app.insert_resource(HighScorePath(std::path::PathBuf::from("high_score.txt")));
+    app
+}
+
+/// Add the 'StatesPlugin' if it is missing, as the states cannot be used without it.
+/// Warns if it had to be added, as the app is likely missing more plugins
+pub fn ensure_states_plugin(app: &mut App) {
+    if !app.is_plugin_added::<bevy::state::app::StatesPlugin>() {
+        warn!(
+            "The StatesPlugin was missing and has been added, add it before adding the game logic"
+        );
         app.add_plugins(bevy::state::app::StatesPlugin);
-    } else {
-        app.add_plugins(DefaultPlugins);
     }
+}
 
+fn add_game_logic(app: &mut App, state: AppState) {
+    ensure_states_plugin(app);
+    register_state::<AppState>(app);
+    register_state::<InGameState>(app);
+    register_state::<IsGameplayActive>(app);
+    register_state::<AudioState>(app);
+    register_state::<PlayerTwoState>(app);
     app
-        .init_state::<AppState>()
+        .insert_state(state)
+        .add_sub_state::<InGameState>()
+        .add_computed_state::<IsGameplayActive>()
+        .init_state::<AudioState>()
+        .init_state::<PlayerTwoState>()
+        .enable_state_scoped_entities::<AppState>()
+        .enable_state_scoped_entities::<InGameState>()
+        .add_event::<GameOver>()
+        .add_event::<StartGameRequested>()
+        .add_event::<InvalidKeyPressed>()
+        .add_event::<PlayerDied>()
+        // Already added by the WindowPlugin, but not in a headless app
+        .add_event::<WindowFocused>()
+        .add_event::<WindowCloseRequested>()
+        .init_resource::<InputMap>()
+        .init_resource::<InputBuffer>()
+        .init_resource::<RebindingStart>()
+        .init_resource::<EscapeTapTracker>()
+        .init_resource::<HoldToStart>()
+        .init_resource::<GameClock>()
+        .init_resource::<ScoreRate>()
+        .init_resource::<WinTarget>()
+        .init_resource::<Difficulty>()
+        .init_resource::<Lives>()
+        .init_resource::<HighScore>()
+        .init_resource::<QuickSave>()
+        .init_resource::<GameSeed>()
+        .init_resource::<TransitionCount>()
+        .init_resource::<Language>()
+        .init_resource::<ClearColor>()
+        .init_resource::<DebugOverlay>()
+        .init_resource::<ShowFps>()
+        .init_resource::<LogInput>()
+        .init_resource::<DebugShortcuts>()
+        .init_resource::<SpawnCamera>()
+        .init_resource::<CameraZoom>()
+        .init_resource::<CameraConfig>()
+        .init_resource::<StateDurations>()
+        .init_resource::<AutoPauseOnBlur>()
+        .init_resource::<MenuSelection>()
+        .init_resource::<PauseMenuSelection>()
+        .init_resource::<NavRepeat>()
+        .init_resource::<StateHistory>()
+        .init_resource::<NavigatingBack>()
+        .insert_resource(LoadingTimer(Timer::from_seconds(2.0, TimerMode::Once)))
         .add_systems(Startup, setup)
-        .add_systems(OnEnter(AppState::Menu), add_menu_text)
-        .add_systems(OnEnter(AppState::InGame), add_game_text)
-        .add_systems(Update, menu_respond_to_keyboard.run_if(in_state(AppState::Menu)))
-        .add_systems(Update, in_game_respond_to_keyboard.run_if(in_state(AppState::InGame)))
-        .add_systems(OnExit(AppState::Menu), despawn_all_text)
-        .add_systems(OnExit(AppState::InGame), despawn_all_text)
-        .add_systems(Update, log_transitions::<AppState>);
+        .add_systems(OnEnter(AppState::Loading), (add_loading_text, reset_loading_timer))
+        .add_systems(
+            OnEnter(AppState::Menu),
+            (add_menu_text, set_menu_clear_color, set_menu_camera_zoom, clear_input_buffer),
+        )
+        .add_systems(
+            OnEnter(AppState::InGame),
+            (
+                add_game_text,
+                seed_game_rng,
+                add_score_text,
+                set_in_game_clear_color,
+                set_in_game_camera_zoom,
+                reset_score,
+                reset_lives,
+                reset_game_clock,
+                (despawn_tagged::<GameplayEntity>, spawn_player).chain(),
+            ),
+        )
+        .add_systems(
+            OnEnter(AppState::InGame),
+            add_fps_text.run_if(|show_fps: Res<ShowFps>| show_fps.0),
+        )
+        .add_systems(
+            OnEnter(AppState::InGame),
+            reset_game_duration.run_if(resource_exists::<GameDuration>),
+        )
+        .add_systems(OnEnter(AppState::InGame), reset_idle_timer.run_if(resource_exists::<IdleTimer>))
+        .add_systems(OnEnter(AppState::Menu), reset_blink_timer.run_if(resource_exists::<BlinkTimer>))
+        .add_systems(OnEnter(InGameState::Paused), (hide_all_text, add_pause_text).chain())
+        .add_systems(OnEnter(InGameState::Paused), add_pause_overlay)
+        .add_systems(OnEnter(AppState::GameOver), (add_game_over_text, update_high_score))
+        .add_systems(OnEnter(AppState::GameOver), store_high_score.run_if(resource_exists::<HighScorePath>))
+        .add_systems(OnEnter(AppState::Settings), add_settings_text)
+        .add_systems(OnExit(AppState::Settings), stop_rebinding)
+        .add_systems(OnEnter(AppState::Victory), add_victory_text)
+        .add_systems(OnEnter(AppState::ConfirmQuit), add_confirm_quit_text)
+        .add_systems(OnEnter(AppState::Countdown), add_countdown_text)
+        .add_systems(OnEnter(AppState::Credits), add_credits_text)
+        .add_systems(Update, advance_loading.run_if(in_state(AppState::Loading)))
+        .add_systems(PreUpdate, record_input.run_if(resource_exists::<InputRecording>))
+        .add_systems(
+            PreUpdate,
+            buffer_key_presses
+                .after(bevy::input::InputSystem)
+                .in_set(TransitionInput)
+                .run_if(in_state(AppState::Menu)),
+        )
+        .add_systems(
+            PreUpdate,
+            tick_transition_cooldown
+                .before(buffer_key_presses)
+                .run_if(resource_exists::<TransitionCooldown>),
+        )
+        .configure_sets(PreUpdate, TransitionInput.run_if(transition_cooldown_finished))
+        .configure_sets(FixedUpdate, TransitionInput.run_if(transition_cooldown_finished))
+        .configure_sets(Update, TransitionInput.run_if(transition_cooldown_finished))
+        .add_systems(
+            Update,
+            menu_respond_to_keyboard
+                .in_set(TransitionInput)
+                .run_if(in_state(AppState::Menu).and(not(resource_exists::<FixedInput>))),
+        )
+        .add_systems(
+            FixedUpdate,
+            menu_respond_to_keyboard
+                .in_set(TransitionInput)
+                .run_if(in_state(AppState::Menu).and(resource_exists::<FixedInput>)),
+        )
+        .add_systems(
+            Update,
+            (menu_respond_to_gamepad, menu_respond_to_mouse, menu_respond_to_touch)
+                .in_set(TransitionInput)
+                .run_if(in_state(AppState::Menu)),
+        )
+        .add_systems(Update, menu_respond_to_logical_keys.in_set(TransitionInput))
+        .add_systems(Update, menu_detect_invalid_keys.run_if(in_state(AppState::Menu)))
+        .add_systems(
+            Update,
+            blink_menu_text.run_if(in_state(AppState::Menu).and(resource_exists::<BlinkTimer>)),
+        )
+        .add_systems(
+            Update,
+            (menu_navigate, menu_cycle_focus, menu_confirm.in_set(TransitionInput))
+                .chain()
+                .run_if(in_state(AppState::Menu)),
+        )
+        .add_systems(
+            Update,
+            handle_start_request
+                .after(menu_respond_to_keyboard)
+                .after(menu_respond_to_gamepad)
+                .after(menu_respond_to_mouse)
+                .after(menu_respond_to_touch)
+                .after(menu_respond_to_logical_keys)
+                .after(menu_confirm)
+                .in_set(TransitionInput)
+                .run_if(in_state(AppState::Menu)),
+        )
+        .add_systems(
+            Update,
+            in_game_respond_to_keyboard
+                .in_set(TransitionInput)
+                .run_if(in_state(InGameState::Playing)),
+        )
+        .add_systems(
+            Update,
+            in_game_respond_to_gamepad
+                .in_set(TransitionInput)
+                .run_if(in_state(AppState::InGame)),
+        )
+        .add_systems(Update, toggle_pause.in_set(TransitionInput))
+        .add_systems(
+            Update,
+            (pause_menu_navigate, pause_menu_confirm.in_set(TransitionInput))
+                .chain()
+                .run_if(in_state(InGameState::Paused)),
+        )
+        .add_systems(
+            Update,
+            pause_on_focus_loss
+                .run_if(in_state(InGameState::Playing))
+                .run_if(|auto_pause: Res<AutoPauseOnBlur>| auto_pause.0),
+        )
+        .add_systems(Update, in_game_respond_to_game_over.run_if(in_state(IsGameplayActive)))
+        .add_systems(Update, lose_life.run_if(in_state(IsGameplayActive)))
+        .add_systems(Update, increment_score.run_if(in_state(InGameState::Playing)))
+        .add_systems(Update, update_score_text.after(increment_score).run_if(in_state(IsGameplayActive)))
+        .add_systems(Update, update_fps_text.run_if(in_state(AppState::InGame)))
+        .add_systems(
+            Update,
+            tick_idle_timer
+                .run_if(in_state(InGameState::Playing))
+                .run_if(resource_exists::<IdleTimer>),
+        )
+        .add_systems(
+            Update,
+            tick_game_duration
+                .run_if(in_state(InGameState::Playing))
+                .run_if(resource_exists::<GameDuration>),
+        )
+        .add_systems(Update, tick_game_clock.run_if(in_state(InGameState::Playing)))
+        .add_systems(Update, quick_save_and_load.run_if(in_state(InGameState::Playing)))
+        .add_systems(Update, move_player.run_if(in_state(InGameState::Playing)))
+        .add_systems(
+            Update,
+            game_over_respond_to_keyboard
+                .in_set(TransitionInput)
+                .run_if(in_state(AppState::GameOver)),
+        )
+        .add_systems(
+            Update,
+            settings_respond_to_keyboard
+                .in_set(TransitionInput)
+                .run_if(in_state(AppState::Settings)),
+        )
+        .add_systems(Update, check_victory.run_if(in_state(IsGameplayActive)))
+        .add_systems(
+            Update,
+            victory_respond_to_keyboard
+                .in_set(TransitionInput)
+                .run_if(in_state(AppState::Victory)),
+        )
+        .add_systems(
+            Update,
+            confirm_quit_respond_to_keyboard
+                .in_set(TransitionInput)
+                .run_if(in_state(AppState::ConfirmQuit)),
+        )
+        .add_systems(
+            Update,
+            quit_on_double_escape
+                .run_if(in_state(AppState::Menu).or(in_state(AppState::ConfirmQuit))),
+        )
+        .add_systems(
+            Update,
+            (count_down, countdown_respond_to_keyboard.in_set(TransitionInput))
+                .chain()
+                .run_if(in_state(AppState::Countdown)),
+        )
+        .add_systems(
+            Update,
+            (scroll_credits, credits_respond_to_keyboard.in_set(TransitionInput))
+                .run_if(in_state(AppState::Credits)),
+        )
+        .add_systems(Update, respond_to_window_close)
+        .add_systems(OnExit(AppState::InGame), despawn_tagged::<GameplayEntity>)
+        .add_systems(OnExit(InGameState::Paused), show_all_text)
+        .add_systems(PostUpdate, fade_screen.after(begin_fade).run_if(resource_exists::<TransitionTimer>))
+        .add_systems(PostUpdate, begin_fade.run_if(resource_exists::<TransitionTimer>))
+        .add_systems(Update, toggle_audio)
+        .add_systems(Update, player_two_respond_to_keyboard)
+        .add_systems(Update, zoom_camera)
+        .add_systems(Update, apply_camera_config.run_if(resource_changed::<CameraConfig>))
+        .add_systems(Update, track_state_durations)
+        .add_systems(Update, log_input.run_if(|log_input: Res<LogInput>| log_input.0))
+        .add_systems(Update, count_transitions)
+        .add_systems(Update, update_state_debug_text.run_if(|overlay: Res<DebugOverlay>| overlay.0))
+        .add_systems(
+            Update,
+            (
+                record_state_history,
+                go_back.in_set(TransitionInput).run_if(is_not_rebinding),
+            )
+                .chain(),
+        )
+        .configure_sets(
+            StateTransition,
+            TransitionLogging.after(bevy::state::state::StateTransitionSteps::EnterSchedules),
+        )
+        .add_systems(StateTransition, log_transitions::<AppState>.in_set(TransitionLogging))
+        .init_resource::<TransitionLog>()
+        .add_systems(StateTransition, record_transitions.in_set(TransitionLogging))
+        .init_resource::<LeftoverTextCount>()
+        .add_systems(
+            StateTransition,
+            check_leftover_texts
+                .after(bevy::state::state::StateTransitionSteps::ExitSchedules)
+                .before(bevy::state::state::StateTransitionSteps::TransitionSchedules)
+                .run_if(|| cfg!(debug_assertions)),
+        );
+    for app_state in all_app_states() {
+        app.add_systems(OnEnter(app_state), set_window_title);
+        app.add_systems(
+            OnEnter(app_state),
+            reset_transition_cooldown.run_if(resource_exists::<TransitionCooldown>),
+        );
+    }
+    for in_game_state in [InGameState::Playing, InGameState::Paused] {
+        app.add_systems(
+            OnEnter(in_game_state),
+            reset_transition_cooldown.run_if(resource_exists::<TransitionCooldown>),
+        );
+    }
+    #[cfg(debug_assertions)]
+    app.add_systems(
+        Update,
+        jump_to_game
+            .in_set(TransitionInput)
+            .run_if(|shortcuts: Res<DebugShortcuts>| shortcuts.0)
+            .run_if(is_not_rebinding),
+    );
+}
 
-    app
+/// Go straight to the game with F1, for debugging.
+/// This is not part of a release build
+#[cfg(debug_assertions)]
+fn jump_to_game(
+    input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if !input.just_pressed(KeyCode::F1) {
+        return;
+    }
+    let current = *state.get();
+    if is_debug_transition(current, AppState::InGame) {
+        next_state.set(AppState::InGame);
+    } else {
+        set_allowed_state(&state, &mut next_state, AppState::InGame);
+    }
+}
+
+/// The number of texts that were left after the last state was exited
+#[derive(Resource, Default)]
+pub struct LeftoverTextCount(pub usize);
+
+/// After exiting a state, all its texts should be gone,
+/// as each text should be scoped to the state that spawned it
+fn check_leftover_texts(
+    mut transitions: EventReader<StateTransitionEvent<AppState>>,
+    query: Query<(), With<Text2d>>,
+    mut leftovers: ResMut<LeftoverTextCount>,
+) {
+    for transition in transitions.read() {
+        if transition.exited == transition.entered {
+            continue;
+        }
+        leftovers.0 = query.iter().len();
+        if leftovers.0 > 0 {
+            error!("{} text(s) left after exiting {:?}", leftovers.0, transition.exited);
+        }
+    }
+}
+
+/// The systems that change the state on any input,
+/// which do not run while the 'TransitionCooldown' is running
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TransitionInput;
+
+/// Logs the state transitions, after they have been applied in the same frame
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TransitionLogging;
+
+fn reset_loading_timer(mut timer: ResMut<LoadingTimer>) {
+    timer.0.reset();
+}
+
+fn advance_loading(
+    time: Res<Time>,
+    mut timer: ResMut<LoadingTimer>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if timer.0.tick(time.delta()).just_finished() {
+        next_state.set(AppState::Menu);
+    }
+}
+
+/// Insert this resource to record the key presses and releases,
+/// with the frame number they happened in.
+/// Use 'save_input_recording' to keep it for a later 'replay_input'
+#[derive(Resource, Default, Clone)]
+pub struct InputRecording(pub Vec<(u64, KeyCode, bevy::input::ButtonState)>);
+
+fn record_input(
+    mut events: EventReader<bevy::input::keyboard::KeyboardInput>,
+    frame_count: Res<bevy::core::FrameCount>,
+    mut recording: ResMut<InputRecording>,
+) {
+    for event in events.read() {
+        recording.0.push((u64::from(frame_count.0), event.key_code, event.state));
+    }
+}
+
+/// Remember the keys pressed in the menu until they are used
+fn buffer_key_presses(
+    mut events: EventReader<bevy::input::keyboard::KeyboardInput>,
+    mut buffer: ResMut<InputBuffer>,
+) {
+    for event in events.read() {
+        let is_new_press = event.state == bevy::input::ButtonState::Pressed && !event.repeat;
+        if is_new_press && !buffer.0.contains(&event.key_code) {
+            buffer.0.push(event.key_code);
+        }
+    }
+}
+
+fn clear_input_buffer(mut buffer: ResMut<InputBuffer>) {
+    buffer.0.clear();
+}
+
+/// The keyboard input of the menu, with the keys that are not used yet
+#[derive(SystemParam)]
+struct MenuKeyboard<'w> {
+    input: Res<'w, ButtonInput<KeyCode>>,
+    input_map: Res<'w, InputMap>,
+    buffer: ResMut<'w, InputBuffer>,
 }
 
 fn menu_respond_to_keyboard(
-    input: Res<ButtonInput<KeyCode>>,
+    keyboard: MenuKeyboard,
+    state: Res<State<AppState>>,
+    selection: Res<MenuSelection>,
+    time: Res<Time>,
+    mut hold_to_start: ResMut<HoldToStart>,
+    mut start_requests: EventWriter<StartGameRequested>,
     mut next_state: ResMut<NextState<AppState>>,
 
 ) {
-    if input.just_pressed(KeyCode::Space) {
-        next_state.set(AppState::InGame);
+    let MenuKeyboard { input, input_map, mut buffer } = keyboard;
+    // Quitting takes precedence over everything else pressed in the same frame
+    if input_map.key(Action::Quit).is_some_and(|key| buffer.take(key)) {
+        buffer.0.clear();
+        set_allowed_state(&state, &mut next_state, AppState::ConfirmQuit);
+        return;
+    }
+    // Enter confirms the highlighted option, so it is a start key when 'Start' is highlighted
+    let enter_starts = MENU_ACTIONS[selection.0] == MenuAction::Start;
+    if hold_to_start.enabled {
+        let is_held = action_pressed(&input, &input_map, Action::Start)
+            || (enter_starts && input.pressed(KeyCode::Enter));
+        if is_held {
+            if hold_to_start.timer.tick(time.delta()).finished() {
+                hold_to_start.timer.reset();
+                start_requests.send(StartGameRequested);
+            }
+        } else {
+            hold_to_start.timer.reset();
+        }
+    } else {
+        // Take both keys, so the other one is not used by a later fixed step
+        let start_pressed = input_map.key(Action::Start).is_some_and(|key| buffer.take(key));
+        let enter_pressed = enter_starts && buffer.take(KeyCode::Enter);
+        if start_pressed || enter_pressed {
+            start_requests.send(StartGameRequested);
+        }
+    }
+    // Take both keys, so the one ignored is not used by a later fixed step
+    let to_settings = buffer.take(KeyCode::KeyS);
+    let to_credits = buffer.take(KeyCode::KeyC);
+    if to_settings {
+        set_allowed_state(&state, &mut next_state, AppState::Settings);
+    } else if to_credits {
+        set_allowed_state(&state, &mut next_state, AppState::Credits);
     }
 }
 
 fn in_game_respond_to_keyboard(
     input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<AppState>>,
+    input_map: Res<InputMap>,
     mut next_state: ResMut<NextState<AppState>>,
 
 ) {
-    if input.just_pressed(KeyCode::Escape) {
-        next_state.set(AppState::Menu);
+    if action_just_pressed(&input, &input_map, Action::Quit) {
+        set_allowed_state(&state, &mut next_state, AppState::Menu);
     }
 }
 
-fn add_game_text(mut commands: Commands) {
-    commands.spawn(Text2d::new("Game. Press escape to quit"));
+fn menu_respond_to_gamepad(
+    gamepads: Query<&Gamepad>,
+    mut start_requests: EventWriter<StartGameRequested>,
+) {
+    if gamepads.iter().any(|gamepad| gamepad.just_pressed(GamepadButton::South)) {
+        start_requests.send(StartGameRequested);
+    }
 }
 
-fn add_menu_text(mut commands: Commands) {
-    commands.spawn(Text2d::new("Menu. Press space to start"));
+fn menu_respond_to_mouse(
+    input: Res<ButtonInput<MouseButton>>,
+    mut start_requests: EventWriter<StartGameRequested>,
+) {
+    if input.just_pressed(MouseButton::Left) {
+        start_requests.send(StartGameRequested);
+    }
 }
 
-#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
-enum AppState {
-    #[default]
-    Menu,
-    InGame,
+/// Start the game when the logical start key is pressed,
+/// e.g. a key that gives a space on another keyboard layout.
+/// The events are read in every state, so no old events are used when entering the menu
+fn menu_respond_to_logical_keys(
+    mut events: EventReader<bevy::input::keyboard::KeyboardInput>,
+    state: Res<State<AppState>>,
+    input_map: Res<InputMap>,
+    hold_to_start: Res<HoldToStart>,
+    mut start_requests: EventWriter<StartGameRequested>,
+) {
+    let is_active = *state.get() == AppState::Menu && !hold_to_start.enabled;
+    let start_key = input_map.key(Action::Start);
+    // A start key without a known logical key, such as F2, is only matched by its 'KeyCode'
+    let logical_start_key = start_key
+        .map(get_logical_key)
+        .filter(|key| !matches!(key, bevy::input::keyboard::Key::Unidentified(_)));
+    for event in events.read() {
+        let is_new_press = event.state == bevy::input::ButtonState::Pressed && !event.repeat;
+        // The physical start key is already used by 'menu_respond_to_keyboard'
+        let is_logical_start = start_key.is_some_and(|key| event.key_code != key)
+            && logical_start_key.as_ref() == Some(&event.logical_key);
+        if is_active && is_new_press && is_logical_start {
+            start_requests.send(StartGameRequested);
+        }
+    }
 }
 
-#[cfg(test)]
-fn count_n_texts(app: &mut App) -> usize {
-    let mut query = app.world_mut().query::<&Text2d>();
-    query.iter(app.world()).len()
+/// A tap anywhere on the screen starts the game
+fn menu_respond_to_touch(
+    touches: Res<Touches>,
+    mut start_requests: EventWriter<StartGameRequested>,
+) {
+    if touches.any_just_pressed() {
+        start_requests.send(StartGameRequested);
+    }
 }
 
-fn setup(mut commands: Commands) {
-    commands.spawn(Camera2d);
-}
+/// The keys that do something in the menu, besides the keys in the 'InputMap'
+const MENU_KEYS: [KeyCode; 12] = [
+    KeyCode::KeyS,
+    KeyCode::KeyC,
+    KeyCode::Enter,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::Backspace,
+    KeyCode::KeyM,
+    KeyCode::Tab,
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::Numpad1,
+    KeyCode::Numpad0,
+];
 
-fn despawn_all_text(
-    mut commands: Commands,
-    query: Query<Entity, With<Text2d>>,
+/// Report the keys that do nothing in the menu
+fn menu_detect_invalid_keys(
+    input: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    shortcuts: Res<DebugShortcuts>,
+    mut invalid_keys: EventWriter<InvalidKeyPressed>,
 ) {
-    for entity in query.iter() {
-        commands.entity(entity).despawn();
+    let mapped_keys = [input_map.key(Action::Start), input_map.key(Action::Quit)];
+    let has_debug_shortcuts = cfg!(debug_assertions) && shortcuts.0;
+    for key in input.get_just_pressed() {
+        let is_debug_shortcut = has_debug_shortcuts && *key == KeyCode::F1;
+        if !MENU_KEYS.contains(key) && !mapped_keys.contains(&Some(*key)) && !is_debug_shortcut {
+            invalid_keys.send(InvalidKeyPressed(*key));
+        }
     }
 }
 
+/// Is the key already used for something else than starting the game?
+fn is_key_in_use(key: KeyCode, input_map: &InputMap) -> bool {
+    MENU_KEYS.contains(&key)
+        || key == KeyCode::F1
+        || input_map
+            .0
+            .iter()
+            .any(|(action, used)| *action != Action::Start && *used == key)
+}
 
-#[cfg(test)]
-fn get_text(app: &mut App) -> String {
-    assert_eq!(count_n_texts(app), 1);
-    let mut query = app.world_mut().query::<&Text2d>();
-    query.single(app.world_mut()).0.clone()
+/// A change between two states that is not allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransitionError {
+    pub from: AppState,
+    pub to: AppState,
 }
 
-#[cfg(test)]
-fn get_program_state(app: &mut App) -> AppState {
-    *app.world_mut().resource_mut::<State<AppState>>().get()
-    /*
-    *world. resource_mut::<State<GameState>>()
-    app.
-    let mut query = app.world_mut().query::<&Text2d>();
-    return query.single(app.world_mut()).sections[0].value.clone();
+impl std::fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "cannot go from {:?} to {:?}", self.from, self.to)
+    }
+}
 
-    game_state: Res<State<GameState>>) {
-        match game_state. get() {
-            GameState::InGame => {
-                // Run game logic here...
-            },
-            _ => {},
-  */
+impl std::error::Error for TransitionError {}
+
+/// Check if the app may go from the current state to the target state
+pub fn try_transition(current: AppState, target: AppState) -> Result<AppState, TransitionError> {
+    let is_allowed = match current {
+        AppState::Loading => target == AppState::Menu,
+        AppState::Menu => matches!(
+            target,
+            AppState::InGame
+                | AppState::Countdown
+                | AppState::Settings
+                | AppState::Credits
+                | AppState::ConfirmQuit
+        ),
+        AppState::Countdown => matches!(target, AppState::InGame | AppState::Menu),
+        AppState::InGame => {
+            matches!(target, AppState::Menu | AppState::GameOver | AppState::Victory)
+        }
+        AppState::GameOver => matches!(target, AppState::InGame | AppState::Menu),
+        AppState::Settings | AppState::Victory | AppState::ConfirmQuit | AppState::Credits => {
+            target == AppState::Menu
+        }
+    };
+    if is_allowed {
+        Ok(target)
+    } else {
+        Err(TransitionError {
+            from: current,
+            to: target,
+        })
+    }
 }
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    #[test]
-    fn test_empty_app_has_text() {
-        let mut app = App::new();
-        app.update();
-        assert_eq!(count_n_texts(&mut app), 0);
+/// What causes an allowed transition, using the default keys
+fn transition_label(from: AppState, to: AppState) -> &'static str {
+    match (from, to) {
+        (AppState::Loading, _) => "loaded",
+        (AppState::Menu, AppState::InGame | AppState::Countdown) => "Space",
+        (AppState::Menu, AppState::Settings) => "S",
+        (AppState::Menu, AppState::Credits) => "C",
+        (AppState::Countdown, AppState::InGame) => "countdown done",
+        (AppState::InGame, AppState::GameOver) => "game over",
+        (AppState::InGame, AppState::Victory) => "target score",
+        (AppState::GameOver, AppState::InGame) => "R",
+        (AppState::GameOver, _) => "Q",
+        (AppState::Victory, _) => "Space",
+        (AppState::ConfirmQuit, _) => "N",
+        _ => "Escape",
     }
+}
 
-    #[test]
-    fn test_app_has_text() {
-        let mut app = create_app();
-        app.update();
-        assert_eq!(count_n_texts(&mut app), 1);
+/// Is this a transition that only the debug shortcuts may make?
+/// F1 jumps to the game from any other state
+pub fn is_debug_transition(current: AppState, target: AppState) -> bool {
+    target == AppState::InGame
+        && current != AppState::InGame
+        && try_transition(current, target).is_err()
+}
+
+/// Get the allowed transitions as a Graphviz DOT graph,
+/// with the states as nodes and the transitions as edges.
+/// The transitions only allowed for the debug shortcuts are dashed
+pub fn export_state_graph_dot() -> String {
+    let mut dot = String::from("digraph AppState {\n");
+    for state in all_app_states() {
+        dot.push_str(&format!("    {:?};\n", state));
     }
+    for from in all_app_states() {
+        for to in all_app_states() {
+            if try_transition(from, to).is_ok() {
+                dot.push_str(&format!(
+                    "    {:?} -> {:?} [label=\"{}\"];\n",
+                    from,
+                    to,
+                    transition_label(from, to)
+                ));
+            } else if is_debug_transition(from, to) {
+                dot.push_str(&format!(
+                    "    {:?} -> {:?} [label=\"F1\", style=dashed];\n",
+                    from, to
+                ));
+            }
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
 
-    #[test]
-    fn test_app_has_menu_text() {
-        let mut app = create_app();
-        app.update();
-        assert_eq!(get_text(&mut app), "Menu. Press space to start");
+/// Request the state change, unless it is not allowed.
+/// Requesting the current state does nothing, so the state is not
+/// needlessly exited and entered again, nor faded out and in
+fn set_allowed_state(
+    state: &State<AppState>,
+    next_state: &mut NextState<AppState>,
+    target: AppState,
+) {
+    if *state.get() == target {
+        return;
+    }
+    match try_transition(*state.get(), target) {
+        Ok(target) => next_state.set(target),
+        Err(error) => warn!("{}", error),
     }
+}
 
-    #[test]
-    fn test_app_starts_at_menu() {
-        let mut app = create_app();
-        app.update();
-        assert_eq!(get_program_state(&mut app), AppState::Menu);
+fn handle_start_request(
+    mut start_requests: EventReader<StartGameRequested>,
+    countdown: Option<Res<CountdownTimer>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if start_requests.read().last().is_some() {
+        let target = if countdown.is_some() { AppState::Countdown } else { AppState::InGame };
+        set_allowed_state(&state, &mut next_state, target);
     }
+}
 
-    #[test]
-    fn test_space_starts_game() {
-        let mut app = create_app();
-        app.update();
-        assert_eq!(get_program_state(&mut app), AppState::Menu);
-        app.world_mut()
-            .send_event(bevy::input::keyboard::KeyboardInput {
-                key_code: KeyCode::Space,
-                logical_key: bevy::input::keyboard::Key::Space,
-                state: bevy::input::ButtonState::Pressed,
-                window: Entity::PLACEHOLDER,
-                repeat: false,
-            });
-        app.update();
-        app.update();
-        assert_eq!(get_program_state(&mut app), AppState::InGame);
+/// Closing the window during a game ends the game first, so the score is saved.
+/// In all other states, the app exits
+fn respond_to_window_close(
+    mut close_requests: EventReader<WindowCloseRequested>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if close_requests.read().last().is_none() {
+        return;
+    }
+    if *state.get() == AppState::InGame {
+        next_state.set(AppState::GameOver);
+    } else {
+        exit.send(AppExit::Success);
     }
+}
 
-    #[test]
-    fn test_escape_leaves_game() {
-        let mut app = create_app();
-        app.update();
-        assert_eq!(get_program_state(&mut app), AppState::Menu);
-        app.world_mut()
-            .send_event(bevy::input::keyboard::KeyboardInput {
-                key_code: KeyCode::Space,
-                logical_key: bevy::input::keyboard::Key::Space,
-                state: bevy::input::ButtonState::Pressed,
-                window: Entity::PLACEHOLDER,
-                repeat: false,
-            });
-        app.update();
-        app.update();
-        assert_eq!(get_program_state(&mut app), AppState::InGame);
-        app.world_mut()
-            .send_event(bevy::input::keyboard::KeyboardInput {
-                key_code: KeyCode::Escape,
-                logical_key: bevy::input::keyboard::Key::Escape,
-                state: bevy::input::ButtonState::Pressed,
-                window: Entity::PLACEHOLDER,
-                repeat: false,
-            });
-        app.update();
-        app.update();
+/// Show the seconds left and start the game when done
+fn count_down(
+    time: Res<Time>,
+    mut countdown: ResMut<CountdownTimer>,
+    mut query: Query<&mut Text2d, With<CountdownText>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    countdown.0.tick(time.delta());
+    if countdown.0.finished() {
+        set_allowed_state(&state, &mut next_state, AppState::InGame);
+        return;
+    }
+    for mut text in &mut query {
+        text.0 = countdown.seconds_left().to_string();
+    }
+}
+
+/// Go back to the menu at escape, also when the countdown is done in the same frame
+fn countdown_respond_to_keyboard(
+    input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if input.just_pressed(KeyCode::Escape) {
+        set_allowed_state(&state, &mut next_state, AppState::Menu);
+    }
+}
+
+fn in_game_respond_to_gamepad(
+    gamepads: Query<&Gamepad>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if gamepads.iter().any(|gamepad| gamepad.just_pressed(GamepadButton::East)) {
+        set_allowed_state(&state, &mut next_state, AppState::Menu);
+    }
+}
+
+fn pause_on_focus_loss(
+    mut focus_events: EventReader<WindowFocused>,
+    mut next_state: ResMut<NextState<InGameState>>,
+) {
+    if focus_events.read().any(|event| !event.focused) {
+        next_state.set(InGameState::Paused);
+    }
+}
+
+/// Pause when playing and resume when paused.
+/// Outside of a game, there is no 'InGameState' and nothing happens
+fn toggle_pause(
+    input: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    state: Option<Res<State<InGameState>>>,
+    mut next_state: ResMut<NextState<InGameState>>,
+) {
+    let Some(state) = state else {
+        return;
+    };
+    if !action_just_pressed(&input, &input_map, Action::Pause) {
+        return;
+    }
+    match state.get() {
+        InGameState::Playing => next_state.set(InGameState::Paused),
+        InGameState::Paused => next_state.set(InGameState::Playing),
+    }
+}
+
+/// Enter starts listening for a new start key, the next key pressed becomes the start key.
+/// Escape cancels listening, or goes back to the menu when not listening
+fn settings_respond_to_keyboard(
+    input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut input_map: ResMut<InputMap>,
+    mut rebinding: ResMut<RebindingStart>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if rebinding.0 {
+        if let Some(&key) = input.get_just_pressed().next() {
+            if key == KeyCode::Escape {
+                rebinding.0 = false;
+            } else if is_key_in_use(key, &input_map) {
+                warn!("Cannot start with {:?}, as it is already in use", key);
+            } else {
+                input_map.0.insert(Action::Start, key);
+                rebinding.0 = false;
+            }
+        }
+        return;
+    }
+    if input.just_pressed(KeyCode::Enter) {
+        rebinding.0 = true;
+    } else if input.just_pressed(KeyCode::Escape) {
+        set_allowed_state(&state, &mut next_state, AppState::Menu);
+    }
+}
+
+fn credits_respond_to_keyboard(
+    input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if input.just_pressed(KeyCode::Escape) {
+        set_allowed_state(&state, &mut next_state, AppState::Menu);
+    }
+}
+
+/// Move the credits up at a constant speed
+fn scroll_credits(time: Res<Time>, mut query: Query<&mut Transform, With<CreditsText>>) {
+    for mut transform in query.iter_mut() {
+        transform.translation.y += CREDITS_SCROLL_SPEED * time.delta_secs();
+    }
+}
+
+fn check_victory(
+    score: Res<Score>,
+    target: Res<WinTarget>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if score.0 >= target.0 {
+        next_state.set(AppState::Victory);
+    }
+}
+
+fn victory_respond_to_keyboard(
+    input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+
+) {
+    if input.just_pressed(KeyCode::Space) {
+        set_allowed_state(&state, &mut next_state, AppState::Menu);
+    }
+}
+
+fn confirm_quit_respond_to_keyboard(
+    input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if input.just_pressed(KeyCode::KeyY) {
+        exit.send(AppExit::Success);
+    } else if input.just_pressed(KeyCode::KeyN) {
+        set_allowed_state(&state, &mut next_state, AppState::Menu);
+    }
+}
+
+/// The maximum number of seconds between two Escape taps to quit
+const DOUBLE_TAP_SECONDS: f32 = 0.4;
+
+/// The first Escape in the menu asks to confirm quitting,
+/// a second Escape shortly after quits without asking
+fn quit_on_double_escape(
+    input: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    mut tracker: ResMut<EscapeTapTracker>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if !input.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    let now = time.elapsed_secs();
+    match tracker.last {
+        Some(last) if now - last <= DOUBLE_TAP_SECONDS => {
+            tracker.last = None;
+            exit.send(AppExit::Success);
+        }
+        _ => tracker.last = Some(now),
+    }
+}
+
+/// Mute or unmute, in any state
+fn toggle_audio(
+    input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<AudioState>>,
+    mut next_state: ResMut<NextState<AudioState>>,
+) {
+    if input.just_pressed(KeyCode::KeyM) {
+        next_state.set(match state.get() {
+            AudioState::On => AudioState::Muted,
+            AudioState::Muted => AudioState::On,
+        });
+    }
+}
+
+/// Let player two start and stop playing with the numpad, in any state
+fn player_two_respond_to_keyboard(
+    input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<PlayerTwoState>>,
+    mut next_state: ResMut<NextState<PlayerTwoState>>,
+) {
+    match state.get() {
+        PlayerTwoState::Menu if input.just_pressed(KeyCode::Numpad1) => {
+            next_state.set(PlayerTwoState::InGame);
+        }
+        PlayerTwoState::InGame if input.just_pressed(KeyCode::Numpad0) => {
+            next_state.set(PlayerTwoState::Menu);
+        }
+        _ => {}
+    }
+}
+
+fn in_game_respond_to_game_over(
+    mut events: EventReader<GameOver>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if events.read().last().is_some() {
+        next_state.set(AppState::GameOver);
+    }
+}
+
+/// Lose a life for each death, it is game over when no lives are left
+fn lose_life(
+    mut events: EventReader<PlayerDied>,
+    mut lives: ResMut<Lives>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for _ in events.read() {
+        lives.0 = lives.0.saturating_sub(1);
+        if lives.0 == 0 {
+            next_state.set(AppState::GameOver);
+        }
+    }
+}
+
+fn game_over_respond_to_keyboard(
+    input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+
+) {
+    if input.just_pressed(KeyCode::KeyR) {
+        set_allowed_state(&state, &mut next_state, AppState::InGame);
+    } else if input.just_pressed(KeyCode::KeyQ) {
+        set_allowed_state(&state, &mut next_state, AppState::Menu);
+    }
+}
+
+/// The high score is kept over games, unlike the score
+fn update_high_score(score: Res<Score>, mut high_score: ResMut<HighScore>) {
+    high_score.0 = high_score.0.max(score.0);
+}
+
+fn store_high_score(score: Res<Score>, path: Res<HighScorePath>) {
+    if let Err(error) = save_high_score(&path.0, score.0) {
+        error!("Could not save high score to {:?}: {}", path.0, error);
+    }
+}
+
+fn spawn_player(mut commands: Commands) {
+    commands.spawn((
+        Player,
+        GameplayEntity,
+        Sprite::from_color(Color::WHITE, Vec2::splat(20.0)),
+        Transform::default(),
+    ));
+}
+
+/// Remove all entities with the marker component, including their children,
+/// e.g. 'despawn_tagged::<GameplayEntity>' so a new game starts clean
+pub fn despawn_tagged<C: Component>(mut commands: Commands, query: Query<Entity, With<C>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn move_player(
+    input: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    mut query: Query<&mut Transform, With<Player>>,
+) {
+    let mut direction = Vec3::ZERO;
+    if input.pressed(KeyCode::KeyW) {
+        direction.y += 1.0;
+    }
+    if input.pressed(KeyCode::KeyS) {
+        direction.y -= 1.0;
+    }
+    if input.pressed(KeyCode::KeyA) {
+        direction.x -= 1.0;
+    }
+    if input.pressed(KeyCode::KeyD) {
+        direction.x += 1.0;
+    }
+    for mut transform in query.iter_mut() {
+        transform.translation += direction * PLAYER_SPEED * time.delta_secs();
+    }
+}
+
+/// The state of a game that can be saved and restored
+#[derive(Clone, Debug, PartialEq)]
+pub struct SaveData {
+    pub score: u32,
+    pub lives: u32,
+    pub game_time: std::time::Duration,
+}
+
+/// The save made with F5, restored with F9
+#[derive(Resource, Default)]
+pub struct QuickSave(pub Option<SaveData>);
+
+/// Get the state of the current game
+pub fn capture_save(world: &World) -> SaveData {
+    SaveData {
+        score: world.resource::<Score>().0,
+        lives: world.resource::<Lives>().0,
+        game_time: world.resource::<GameClock>().0.elapsed(),
+    }
+}
+
+/// Restore the state of a game
+pub fn apply_save(world: &mut World, save: &SaveData) {
+    world.resource_mut::<Score>().0 = save.score;
+    world.resource_mut::<Lives>().0 = save.lives;
+    world.resource_mut::<GameClock>().0.set_elapsed(save.game_time);
+}
+
+fn quick_save_and_load(world: &mut World) {
+    let input = world.resource::<ButtonInput<KeyCode>>();
+    if input.just_pressed(KeyCode::F5) {
+        let save = capture_save(world);
+        world.resource_mut::<QuickSave>().0 = Some(save);
+    } else if input.just_pressed(KeyCode::F9) {
+        if let Some(save) = world.resource::<QuickSave>().0.clone() {
+            apply_save(world, &save);
+        }
+    }
+}
+
+fn reset_game_clock(mut clock: ResMut<GameClock>) {
+    clock.0.reset();
+}
+
+fn tick_game_clock(time: Res<Time>, mut clock: ResMut<GameClock>) {
+    clock.0.tick(time.delta());
+}
+
+/// Increase the score at a fixed rate per second,
+/// keeping the fractions of points for the next frame
+fn increment_score(
+    time: Res<Time>,
+    difficulty: Res<Difficulty>,
+    mut rate: ResMut<ScoreRate>,
+    mut score: ResMut<Score>,
+) {
+    rate.accumulator += rate.per_second * difficulty.score_multiplier() * time.delta_secs();
+    let points = rate.accumulator.floor();
+    rate.accumulator -= points;
+    score.0 += points as u32;
+}
+
+/// The score is a UI text in a corner, so it is not counted as a Text2d
+fn add_score_text(mut commands: Commands) {
+    commands.spawn((
+        ScoreText,
+        Text::new("Score: 0"),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            right: Val::Px(5.0),
+            ..default()
+        },
+        StateScoped(AppState::InGame),
+    ));
+}
+
+fn update_score_text(score: Res<Score>, mut query: Query<&mut Text, With<ScoreText>>) {
+    for mut text in query.iter_mut() {
+        text.0 = format!("Score: {}", score.0);
+    }
+}
+
+fn reset_idle_timer(mut idle_timer: ResMut<IdleTimer>) {
+    idle_timer.0.reset();
+}
+
+fn reset_transition_cooldown(mut cooldown: ResMut<TransitionCooldown>) {
+    cooldown.0.reset();
+}
+
+fn tick_transition_cooldown(time: Res<Time>, mut cooldown: ResMut<TransitionCooldown>) {
+    cooldown.0.tick(time.delta());
+}
+
+/// Without a 'TransitionCooldown', there is never a cooldown
+fn transition_cooldown_finished(cooldown: Option<Res<TransitionCooldown>>) -> bool {
+    match cooldown {
+        Some(cooldown) => cooldown.0.finished(),
+        None => true,
+    }
+}
+
+fn reset_blink_timer(mut blink_timer: ResMut<BlinkTimer>) {
+    blink_timer.0.reset();
+}
+
+/// Show the menu text when it is hidden and hide it when it is shown
+fn blink_menu_text(
+    time: Res<Time>,
+    mut blink_timer: ResMut<BlinkTimer>,
+    mut query: Query<&mut Visibility, With<MenuText>>,
+) {
+    if !blink_timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    for mut visibility in &mut query {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Visible,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+/// Go back to the menu when there has been no input for a while
+fn tick_idle_timer(
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    time: Res<Time>,
+    mut idle_timer: ResMut<IdleTimer>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let has_input = keys.get_pressed().len() > 0
+        || keys.get_just_pressed().len() > 0
+        || mouse_buttons.get_pressed().len() > 0
+        || mouse_buttons.get_just_pressed().len() > 0;
+    if has_input {
+        idle_timer.0.reset();
+    } else if idle_timer.0.tick(time.delta()).just_finished() {
+        next_state.set(AppState::Menu);
+    }
+}
+
+fn reset_game_duration(mut duration: ResMut<GameDuration>) {
+    duration.0.reset();
+}
+
+/// The game is over when its time is up
+fn tick_game_duration(
+    time: Res<Time>,
+    mut duration: ResMut<GameDuration>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if duration.0.tick(time.delta()).just_finished() {
+        next_state.set(AppState::GameOver);
+    }
+}
+
+fn add_fps_text(mut commands: Commands) {
+    commands.spawn((
+        FpsText,
+        Text::new("FPS: 0"),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.0),
+            right: Val::Px(5.0),
+            ..default()
+        },
+        StateScoped(AppState::InGame),
+    ));
+}
+
+fn update_fps_text(time: Res<Time>, mut query: Query<&mut Text, With<FpsText>>) {
+    let delta = time.delta_secs();
+    if delta <= 0.0 {
+        return;
+    }
+    for mut text in query.iter_mut() {
+        text.0 = format!("FPS: {}", (1.0 / delta).round());
+    }
+}
+
+/// Every game starts with the same random numbers for the same seed
+fn seed_game_rng(mut commands: Commands, seed: Res<GameSeed>) {
+    commands.insert_resource(GameRng(StdRng::seed_from_u64(seed.0)));
+}
+
+fn reset_score(mut score: ResMut<Score>, mut rate: ResMut<ScoreRate>) {
+    score.0 = 0;
+    rate.accumulator = 0.0;
+}
+
+fn reset_lives(mut lives: ResMut<Lives>) {
+    *lives = Lives::default();
+}
+
+/// Postpone a requested state change until the screen has faded to black.
+/// Requests made during a fade are ignored, so input while fading does nothing.
+/// Runs after all input systems, so it sees the request in the same frame
+fn begin_fade(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut fade: ResMut<TransitionTimer>,
+) {
+    let NextState::Pending(target) = next_state.as_ref() else {
+        return;
+    };
+    let target = *target;
+    next_state.reset();
+    if fade.target.is_some() {
+        return;
+    }
+    fade.target = Some(target);
+    fade.source = *state.get();
+    fade.fading_in = false;
+    fade.timer.reset();
+    commands.spawn((
+        FadeOverlay,
+        Sprite::from_color(Color::srgba(0.0, 0.0, 0.0, 0.0), Vec2::splat(10000.0)),
+        Transform::from_xyz(0.0, 0.0, 100.0),
+    ));
+}
+
+/// Runs after 'begin_fade', so the state change at the end of the fade-out is kept
+fn fade_screen(
+    mut commands: Commands,
+    time: Res<Time>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut fade: ResMut<TransitionTimer>,
+    mut query: Query<(Entity, &mut Sprite), With<FadeOverlay>>,
+) {
+    let Some(target) = fade.target else {
+        return;
+    };
+    fade.timer.tick(time.delta());
+    let fraction = fade.timer.fraction();
+    let alpha = if fade.fading_in { 1.0 - fraction } else { fraction };
+    for (_, mut sprite) in query.iter_mut() {
+        sprite.color = Color::srgba(0.0, 0.0, 0.0, alpha);
+    }
+    if !fade.timer.finished() {
+        return;
+    }
+    if fade.fading_in {
+        for (entity, _) in query.iter() {
+            commands.entity(entity).despawn();
+        }
+        fade.target = None;
+    } else {
+        // The state changed during the fade, so the target may no longer make sense
+        if *state.get() == fade.source {
+            next_state.set(target);
+        } else {
+            warn!("Dropped the fade to {:?}, as the state changed to {:?}", target, state.get());
+        }
+        fade.fading_in = true;
+        fade.timer.reset();
+    }
+}
+
+/// Count the state changes, ignoring the initial state
+/// and transitions from a state to itself
+fn count_transitions(
+    mut transitions: EventReader<StateTransitionEvent<AppState>>,
+    mut count: ResMut<TransitionCount>,
+) {
+    for transition in transitions.read() {
+        if transition.exited.is_some() && transition.exited != transition.entered {
+            count.0 += 1;
+        }
+    }
+}
+
+/// Remember the previous states, except when going back to one
+fn record_state_history(
+    mut transitions: EventReader<StateTransitionEvent<AppState>>,
+    mut history: ResMut<StateHistory>,
+    mut navigating_back: ResMut<NavigatingBack>,
+) {
+    for transition in transitions.read() {
+        let Some(exited) = transition.exited else {
+            continue;
+        };
+        if Some(exited) == transition.entered {
+            continue;
+        }
+        if navigating_back.0 {
+            navigating_back.0 = false;
+        } else {
+            history.0.push(exited);
+        }
+    }
+}
+
+/// Go back to the previous state, if that transition is allowed.
+/// If it is not, the history is kept as it is
+fn go_back(
+    input: Res<ButtonInput<KeyCode>>,
+    mut history: ResMut<StateHistory>,
+    mut navigating_back: ResMut<NavigatingBack>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if !input.just_pressed(KeyCode::Backspace) {
+        return;
+    }
+    let Some(&previous) = history.0.last() else {
+        return;
+    };
+    match try_transition(*state.get(), previous) {
+        Ok(previous) => {
+            history.0.pop();
+            navigating_back.0 = true;
+            next_state.set(previous);
+        }
+        Err(error) => warn!("Cannot go back: {}", error),
+    }
+}
+
+fn set_menu_clear_color(mut clear_color: ResMut<ClearColor>) {
+    clear_color.0 = MENU_CLEAR_COLOR;
+}
+
+fn set_in_game_clear_color(mut clear_color: ResMut<ClearColor>) {
+    clear_color.0 = IN_GAME_CLEAR_COLOR;
+}
+
+/// Spawn a text in the center of the screen
+fn spawn_centered_text<'a>(
+    commands: &'a mut Commands,
+    content: &str,
+) -> bevy::ecs::system::EntityCommands<'a> {
+    commands.spawn((
+        Text2d::new(content),
+        TextLayout::new_with_justify(JustifyText::Center),
+    ))
+}
+
+fn add_loading_text(mut commands: Commands) {
+    spawn_centered_text(&mut commands, "Loading...").insert(StateScoped(AppState::Loading));
+}
+
+fn add_game_text(
+    mut commands: Commands,
+    language: Res<Language>,
+    custom_strings: Option<Res<UiStrings>>,
+) {
+    let strings = get_ui_strings(*language, custom_strings.as_deref());
+    spawn_centered_text(&mut commands, &strings.game).insert(StateScoped(AppState::InGame));
+}
+
+/// Dim the game while paused
+fn add_pause_overlay(mut commands: Commands) {
+    commands.spawn((
+        PauseOverlay,
+        Sprite::from_color(Color::srgba(0.0, 0.0, 0.0, 0.5), Vec2::splat(10000.0)),
+        Transform::from_xyz(0.0, 0.0, 50.0),
+        StateScoped(InGameState::Paused),
+    ));
+}
+
+fn add_pause_text(mut commands: Commands, mut selection: ResMut<PauseMenuSelection>) {
+    selection.0 = 0;
+    spawn_centered_text(&mut commands, "Paused. Press P to resume")
+        // In front of the pause overlay
+        .insert((StateScoped(InGameState::Paused), Transform::from_xyz(0.0, 0.0, 100.0)))
+        .with_children(|parent| {
+            for index in 0..PAUSE_MENU_OPTIONS.len() {
+                parent.spawn((
+                    TextSpan::new(option_text(&PAUSE_MENU_OPTIONS, index, selection.0)),
+                    PauseMenuOption(index),
+                ));
+            }
+        });
+}
+
+fn pause_menu_navigate(
+    input: Res<ButtonInput<KeyCode>>,
+    mut selection: ResMut<PauseMenuSelection>,
+    mut query: Query<(&PauseMenuOption, &mut TextSpan)>,
+) {
+    let n_options = PAUSE_MENU_OPTIONS.len();
+    if input.just_pressed(KeyCode::ArrowDown) {
+        selection.0 = (selection.0 + 1) % n_options;
+    } else if input.just_pressed(KeyCode::ArrowUp) {
+        selection.0 = (selection.0 + n_options - 1) % n_options;
+    } else {
+        return;
+    }
+    for (option, mut span) in query.iter_mut() {
+        span.0 = option_text(&PAUSE_MENU_OPTIONS, option.0, selection.0);
+    }
+}
+
+fn pause_menu_confirm(
+    input: Res<ButtonInput<KeyCode>>,
+    selection: Res<PauseMenuSelection>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut next_in_game_state: ResMut<NextState<InGameState>>,
+) {
+    if !input.just_pressed(KeyCode::Enter) {
+        return;
+    }
+    match PAUSE_ACTIONS[selection.0] {
+        PauseAction::Resume => {
+            next_in_game_state.set(InGameState::Playing);
+        }
+        PauseAction::QuitToMenu => {
+            set_allowed_state(&state, &mut next_state, AppState::Menu);
+        }
+    }
+}
+
+fn add_game_over_text(mut commands: Commands) {
+    spawn_centered_text(&mut commands, "Game over. Press R to restart or Q for menu")
+        .insert(StateScoped(AppState::GameOver));
+}
+
+fn add_credits_text(mut commands: Commands) {
+    spawn_centered_text(&mut commands, CREDITS_TEXT)
+        .insert((CreditsText, StateScoped(AppState::Credits)));
+}
+
+fn add_settings_text(mut commands: Commands) {
+    spawn_centered_text(&mut commands, "Settings. Press escape to go back")
+        .insert(StateScoped(AppState::Settings));
+}
+
+fn add_victory_text(mut commands: Commands) {
+    spawn_centered_text(&mut commands, "You win! Press space for menu")
+        .insert(StateScoped(AppState::Victory));
+}
+
+fn add_confirm_quit_text(mut commands: Commands) {
+    spawn_centered_text(&mut commands, "Quit? Y/N").insert(StateScoped(AppState::ConfirmQuit));
+}
+
+fn add_countdown_text(mut commands: Commands, mut countdown: ResMut<CountdownTimer>) {
+    countdown.0.reset();
+    spawn_centered_text(&mut commands, &countdown.seconds_left().to_string())
+        .insert((CountdownText, StateScoped(AppState::Countdown)));
+}
+
+/// The text shown in the credits
+const CREDITS_TEXT: &str = "Credits\n\nMade with Bevy\n\nPress escape to go back";
+
+/// The speed at which the credits move up, in pixels per second
+const CREDITS_SCROLL_SPEED: f32 = 20.0;
+
+/// The first part of the window title, the state is shown after it
+const WINDOW_TITLE: &str = "Use game state";
+
+/// Show the state in the window title. Without a window, nothing happens
+fn set_window_title(
+    state: Res<State<AppState>>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    for mut window in windows.iter_mut() {
+        window.title = format!("{} - {:?}", WINDOW_TITLE, state.get());
+    }
+}
+
+/// The camera scale in the menu, which is zoomed out
+pub const MENU_CAMERA_SCALE: f32 = 1.5;
+
+/// The camera scale while in game
+pub const IN_GAME_CAMERA_SCALE: f32 = 1.0;
+
+/// How fast the camera zooms, as the fraction of the remaining zoom per second
+const CAMERA_ZOOM_SPEED: f32 = 5.0;
+
+fn apply_camera_config(config: Res<CameraConfig>, mut cameras: Query<&mut Camera>) {
+    for mut camera in &mut cameras {
+        camera.clear_color = match (config.clear, config.color) {
+            (false, _) => ClearColorConfig::None,
+            (true, Some(color)) => ClearColorConfig::Custom(color),
+            (true, None) => ClearColorConfig::Default,
+        };
+    }
+}
+
+fn set_menu_camera_zoom(mut zoom: ResMut<CameraZoom>) {
+    zoom.target = MENU_CAMERA_SCALE;
+}
+
+fn set_in_game_camera_zoom(mut zoom: ResMut<CameraZoom>) {
+    zoom.target = IN_GAME_CAMERA_SCALE;
+}
+
+/// Add the time of this frame to the time spent in the current state
+fn track_state_durations(
+    time: Res<Time>,
+    state: Res<State<AppState>>,
+    mut durations: ResMut<StateDurations>,
+) {
+    *durations.0.entry(*state.get()).or_default() += time.delta_secs();
+}
+
+/// Move the camera scale smoothly towards its target
+fn zoom_camera(
+    time: Res<Time>,
+    zoom: Res<CameraZoom>,
+    mut query: Query<&mut OrthographicProjection, With<Camera2d>>,
+) {
+    let fraction = (CAMERA_ZOOM_SPEED * time.delta_secs()).min(1.0);
+    for mut projection in query.iter_mut() {
+        projection.scale += (zoom.target - projection.scale) * fraction;
+    }
+}
+
+/// The background color in the menu
+pub const MENU_CLEAR_COLOR: Color = Color::srgb(0.0, 0.0, 0.3);
+
+/// The background color while in game
+pub const IN_GAME_CLEAR_COLOR: Color = Color::BLACK;
+
+/// The options in the menu, in the order shown
+const MENU_OPTIONS: [&str; 2] = ["Start", "Quit"];
+
+/// The action of each option in the menu
+const MENU_ACTIONS: [MenuAction; 2] = [MenuAction::Start, MenuAction::Quit];
+
+/// The options in the pause menu, in the order shown
+const PAUSE_MENU_OPTIONS: [&str; 2] = ["Resume", "Quit to menu"];
+
+/// The action of each option in the pause menu
+const PAUSE_ACTIONS: [PauseAction; 2] = [PauseAction::Resume, PauseAction::QuitToMenu];
+
+fn option_text(options: &[&str], index: usize, selected: usize) -> String {
+    let cursor = if index == selected { ">" } else { " " };
+    format!("\n{} {}", cursor, options[index])
+}
+
+/// The options are a UI text below the prompt, so that the menu is still one single Text2d
+/// and blinking the prompt leaves the options visible
+fn add_menu_text(
+    mut commands: Commands,
+    language: Res<Language>,
+    custom_strings: Option<Res<UiStrings>>,
+    mut selection: ResMut<MenuSelection>,
+) {
+    selection.0 = 0;
+    let strings = get_ui_strings(*language, custom_strings.as_deref());
+    spawn_centered_text(&mut commands, &strings.menu)
+        .insert((MenuText, StateScoped(AppState::Menu)));
+    commands
+        .spawn((
+            Text::default(),
+            TextLayout::new_with_justify(JustifyText::Center),
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(50.0),
+                width: Val::Percent(100.0),
+                ..default()
+            },
+            StateScoped(AppState::Menu),
+        ))
+        .with_children(|parent| {
+            for index in 0..MENU_OPTIONS.len() {
+                parent.spawn((
+                    TextSpan::new(option_text(&MENU_OPTIONS, index, selection.0)),
+                    MenuOption(index),
+                    Focusable { index },
+                ));
+            }
+        });
+}
+
+/// Move the selection when an arrow key is pressed,
+/// and keep moving it while the key is held
+fn menu_navigate(
+    input: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    mut repeat: ResMut<NavRepeat>,
+    mut selection: ResMut<MenuSelection>,
+    mut query: Query<(&MenuOption, &mut TextSpan)>,
+) {
+    let n_options = MENU_OPTIONS.len();
+    let is_down = |key| input.pressed(key) || input.just_pressed(key);
+    let (key, step) = if is_down(KeyCode::ArrowDown) {
+        (KeyCode::ArrowDown, 1)
+    } else if is_down(KeyCode::ArrowUp) {
+        (KeyCode::ArrowUp, n_options - 1)
+    } else {
+        repeat.delay.reset();
+        repeat.interval.reset();
+        return;
+    };
+    let must_move = if input.just_pressed(key) {
+        repeat.delay.reset();
+        repeat.interval.reset();
+        true
+    } else if !repeat.delay.finished() {
+        repeat.delay.tick(time.delta()).just_finished()
+    } else {
+        repeat.interval.tick(time.delta()).just_finished()
+    };
+    if !must_move {
+        return;
+    }
+    selection.0 = (selection.0 + step) % n_options;
+    for (option, mut span) in query.iter_mut() {
+        span.0 = option_text(&MENU_OPTIONS, option.0, selection.0);
+    }
+}
+
+/// Move the focus to the next item with Tab, or the previous item with Shift+Tab
+fn menu_cycle_focus(
+    input: Res<ButtonInput<KeyCode>>,
+    mut selection: ResMut<MenuSelection>,
+    mut query: Query<(&Focusable, &mut TextSpan)>,
+) {
+    if !input.just_pressed(KeyCode::Tab) {
+        return;
+    }
+    let n_items = query.iter().len();
+    if n_items == 0 {
+        return;
+    }
+    let backwards = input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let step = if backwards { n_items - 1 } else { 1 };
+    selection.0 = (selection.0 + step) % n_items;
+    for (focusable, mut span) in query.iter_mut() {
+        span.0 = option_text(&MENU_OPTIONS, focusable.index, selection.0);
+    }
+}
+
+fn menu_confirm(
+    input: Res<ButtonInput<KeyCode>>,
+    selection: Res<MenuSelection>,
+    mut start_requests: EventWriter<StartGameRequested>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if !input.just_pressed(KeyCode::Enter) {
+        return;
+    }
+    match MENU_ACTIONS[selection.0] {
+        // Enter then works like the start key, see 'menu_respond_to_keyboard'
+        MenuAction::Start => {}
+        action => perform_menu_action(action, &mut start_requests, &mut exit),
+    }
+}
+
+/// What choosing an item in the pause menu does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseAction {
+    Resume,
+    QuitToMenu,
+}
+
+/// What choosing an item in the menu does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAction {
+    Start,
+    Quit,
+}
+
+/// Do what a menu item is for.
+/// Starting goes through a 'StartGameRequested', just like the start key,
+/// so it gets the countdown and fade too
+pub fn perform_menu_action(
+    action: MenuAction,
+    start_requests: &mut EventWriter<StartGameRequested>,
+    exit: &mut EventWriter<AppExit>,
+) {
+    match action {
+        MenuAction::Start => {
+            start_requests.send(StartGameRequested);
+        }
+        MenuAction::Quit => {
+            exit.send(AppExit::Success);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
+pub enum AppState {
+    #[default]
+    Loading,
+    Menu,
+    InGame,
+    GameOver,
+    Settings,
+    Victory,
+    ConfirmQuit,
+    Countdown,
+    Credits,
+}
+
+/// All states of the app, in the order they are declared
+pub fn all_app_states() -> Vec<AppState> {
+    vec![
+        AppState::Loading,
+        AppState::Menu,
+        AppState::InGame,
+        AppState::GameOver,
+        AppState::Settings,
+        AppState::Victory,
+        AppState::ConfirmQuit,
+        AppState::Countdown,
+        AppState::Credits,
+    ]
+}
+
+/// The text shown in each state, as found by starting an app in that state.
+/// This builds a whole app per state, so it is only used by the tests
+#[cfg(test)]
+fn text_catalog() -> Vec<(AppState, String)> {
+    all_app_states()
+        .into_iter()
+        .map(|state| {
+            let mut app = create_app_in_state(state);
+            // Entering the countdown needs its timer
+            app.insert_resource(CountdownTimer::new(3.0));
+            app.update();
+            (state, get_text(&mut app))
+        })
+        .collect()
+}
+
+/// Is the audio on? This state is independent of 'AppState'
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
+pub enum AudioState {
+    #[default]
+    On,
+    Muted,
+}
+
+/// Is player two playing? This state is independent of 'AppState'
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
+pub enum PlayerTwoState {
+    #[default]
+    Menu,
+    InGame,
+}
+
+/// The state within a game, which only exists while in game
+#[derive(SubStates, Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+#[source(AppState = AppState::InGame)]
+pub enum InGameState {
+    #[default]
+    Playing,
+    Paused,
+}
+
+/// The index of the selected option in the menu
+#[derive(Resource, Default)]
+pub struct MenuSelection(pub usize);
+
+/// The index of the selected option in the pause menu
+#[derive(Resource, Default)]
+pub struct PauseMenuSelection(pub usize);
+
+/// Times the repeated moves of the menu selection while an arrow key is held
+#[derive(Resource)]
+pub struct NavRepeat {
+    /// The time before the first repeat
+    pub delay: Timer,
+    /// The time between two repeats
+    pub interval: Timer,
+}
+
+impl Default for NavRepeat {
+    fn default() -> Self {
+        Self {
+            delay: Timer::from_seconds(0.5, TimerMode::Once),
+            interval: Timer::from_seconds(0.1, TimerMode::Repeating),
+        }
+    }
+}
+
+/// An option in the menu, with its index
+#[derive(Component)]
+pub struct MenuOption(pub usize);
+
+/// An item that can get the focus with Tab, with its index in the tab order
+#[derive(Component)]
+pub struct Focusable {
+    pub index: usize,
+}
+
+/// The scrolling text in the credits
+#[derive(Component)]
+pub struct CreditsText;
+
+/// The text in the menu
+#[derive(Component)]
+pub struct MenuText;
+
+/// The translucent sprite that dims the game while paused
+#[derive(Component)]
+pub struct PauseOverlay;
+
+/// An option in the pause menu, with its index
+#[derive(Component)]
+pub struct PauseMenuOption(pub usize);
+
+/// Exists while the game is played, also when paused
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct IsGameplayActive;
+
+impl ComputedStates for IsGameplayActive {
+    type SourceStates = AppState;
+
+    fn compute(sources: AppState) -> Option<Self> {
+        match sources {
+            AppState::InGame => Some(Self),
+            _ => None,
+        }
+    }
+}
+
+/// Marks the entities that belong to a game,
+/// which are removed when a game ends or starts
+#[derive(Component)]
+pub struct GameplayEntity;
+
+/// The player, which only exists while in game
+#[derive(Component)]
+pub struct Player;
+
+/// The speed of the player, in pixels per second
+const PLAYER_SPEED: f32 = 200.0;
+
+/// The time played in the current game, which stands still while paused
+#[derive(Resource, Default)]
+pub struct GameClock(pub Stopwatch);
+
+/// The seed of the random number generator of each game
+#[derive(Resource, Default)]
+pub struct GameSeed(pub u64);
+
+/// The random number generator of the current game
+#[derive(Resource)]
+pub struct GameRng(pub StdRng);
+
+/// The text that shows the score while in game
+#[derive(Component)]
+pub struct ScoreText;
+
+/// How fast the score increases while playing
+#[derive(Resource)]
+pub struct ScoreRate {
+    /// The number of points per second
+    pub per_second: f32,
+    /// The fraction of a point not yet added to the score
+    pub accumulator: f32,
+}
+
+impl Default for ScoreRate {
+    fn default() -> Self {
+        Self::new(10.0)
+    }
+}
+
+impl ScoreRate {
+    pub fn new(per_second: f32) -> Self {
+        Self {
+            per_second,
+            accumulator: 0.0,
+        }
+    }
+}
+
+/// How hard the game is, the harder the faster the score increases
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// The factor the score rate is multiplied with
+    pub fn score_multiplier(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.5,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 2.0,
+        }
+    }
+}
+
+/// The score at which the game is won
+#[derive(Resource)]
+pub struct WinTarget(pub u32);
+
+impl Default for WinTarget {
+    fn default() -> Self {
+        Self(100)
+    }
+}
+
+/// The score of the current game, increased while playing
+#[derive(Resource, Default)]
+pub struct Score(pub u32);
+
+/// The highest score of all games since the app started
+#[derive(Resource, Default)]
+pub struct HighScore(pub u32);
+
+/// When enabled, the start key must be held for some time to start a game
+#[derive(Resource)]
+pub struct HoldToStart {
+    pub timer: Timer,
+    pub enabled: bool,
+}
+
+impl Default for HoldToStart {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(1.0, TimerMode::Once),
+            enabled: false,
+        }
+    }
+}
+
+/// Insert this resource to save the high score to this file at game over
+#[derive(Resource)]
+pub struct HighScorePath(pub std::path::PathBuf);
+
+/// The time of the last Escape tap, to detect a double tap
+#[derive(Resource, Default)]
+pub struct EscapeTapTracker {
+    pub last: Option<f32>,
+}
+
+/// Is the settings screen waiting for the new start key?
+#[derive(Resource, Default)]
+pub struct RebindingStart(pub bool);
+
+/// While rebinding, the pressed key is the new start key and does nothing else
+fn is_not_rebinding(rebinding: Res<RebindingStart>) -> bool {
+    !rebinding.0
+}
+
+/// Leaving the settings in any way cancels the rebinding
+fn stop_rebinding(mut rebinding: ResMut<RebindingStart>) {
+    rebinding.0 = false;
+}
+
+/// Insert this resource to respond to the keyboard in the menu in 'FixedUpdate'
+/// instead of 'Update', so that the frame rate does not matter.
+/// The keys are taken from the 'InputBuffer', so each key press is used once,
+/// whether there are zero or multiple fixed steps in a frame
+#[derive(Resource)]
+pub struct FixedInput;
+
+/// The keys pressed in the menu that have not been used yet,
+/// so that no key press is lost between frames
+#[derive(Resource, Default)]
+pub struct InputBuffer(pub Vec<KeyCode>);
+
+impl InputBuffer {
+    /// Remove the key from the buffer, returning whether it was there
+    pub fn take(&mut self, key: KeyCode) -> bool {
+        let n_before = self.0.len();
+        self.0.retain(|buffered| *buffered != key);
+        self.0.len() != n_before
+    }
+}
+
+/// The things a player can do, independent of the key used
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Start,
+    Quit,
+    Pause,
+}
+
+/// The key used for each action
+#[derive(Resource)]
+pub struct InputMap(pub bevy::utils::HashMap<Action, KeyCode>);
+
+impl InputMap {
+    /// The key for the action, if any
+    pub fn key(&self, action: Action) -> Option<KeyCode> {
+        self.0.get(&action).copied()
+    }
+
+    /// Use another key for the action
+    pub fn with(mut self, action: Action, key: KeyCode) -> Self {
+        self.0.insert(action, key);
+        self
+    }
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self(bevy::utils::HashMap::from_iter([
+            (Action::Start, KeyCode::Space),
+            (Action::Quit, KeyCode::Escape),
+            (Action::Pause, KeyCode::KeyP),
+        ]))
+    }
+}
+
+/// The keys to start and quit a game.
+/// This is a shorthand for the 'Start' and 'Quit' actions of an 'InputMap'
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBindings {
+    pub start: KeyCode,
+    pub quit: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            start: KeyCode::Space,
+            quit: KeyCode::Escape,
+        }
+    }
+}
+
+impl From<KeyBindings> for InputMap {
+    fn from(bindings: KeyBindings) -> Self {
+        InputMap::default()
+            .with(Action::Start, bindings.start)
+            .with(Action::Quit, bindings.quit)
+    }
+}
+
+impl From<&InputMap> for KeyBindings {
+    fn from(map: &InputMap) -> Self {
+        let defaults = KeyBindings::default();
+        Self {
+            start: map.key(Action::Start).unwrap_or(defaults.start),
+            quit: map.key(Action::Quit).unwrap_or(defaults.quit),
+        }
+    }
+}
+
+/// Is the key for this action just pressed?
+pub fn action_just_pressed(input: &ButtonInput<KeyCode>, map: &InputMap, action: Action) -> bool {
+    map.key(action).is_some_and(|key| input.just_pressed(key))
+}
+
+/// Is the key for this action held down?
+fn action_pressed(input: &ButtonInput<KeyCode>, map: &InputMap, action: Action) -> bool {
+    map.key(action).is_some_and(|key| input.pressed(key))
+}
+
+/// Times the fade to black before a state change and the fade back afterwards
+#[derive(Resource)]
+pub struct TransitionTimer {
+    timer: Timer,
+    target: Option<AppState>,
+    source: AppState,
+    fading_in: bool,
+}
+
+impl TransitionTimer {
+    /// Fade out and in again, each taking the given number of seconds
+    pub fn new(seconds: f32) -> Self {
+        Self {
+            timer: Timer::from_seconds(seconds, TimerMode::Once),
+            target: None,
+            source: AppState::default(),
+            fading_in: false,
+        }
+    }
+}
+
+/// Insert this resource to go back to the menu when there is no input in a game
+#[derive(Resource)]
+pub struct IdleTimer(pub Timer);
+
+impl IdleTimer {
+    /// Time out after the given number of seconds without input
+    pub fn new(seconds: f32) -> Self {
+        Self(Timer::from_seconds(seconds, TimerMode::Once))
+    }
+}
+
+/// Insert this resource to ignore keyboard input shortly after a state change,
+/// to prevent flickering between states
+#[derive(Resource)]
+pub struct TransitionCooldown(pub Timer);
+
+impl TransitionCooldown {
+    /// Ignore input for the given number of seconds
+    pub fn new(seconds: f32) -> Self {
+        Self(Timer::from_seconds(seconds, TimerMode::Once))
+    }
+}
+
+/// Insert this resource to let the menu text blink
+#[derive(Resource)]
+pub struct BlinkTimer(pub Timer);
+
+impl BlinkTimer {
+    /// Show or hide the text every given number of seconds
+    pub fn new(seconds: f32) -> Self {
+        Self(Timer::from_seconds(seconds, TimerMode::Repeating))
+    }
+}
+
+/// Insert this resource to end each game after a fixed time
+#[derive(Resource)]
+pub struct GameDuration(pub Timer);
+
+impl GameDuration {
+    /// A game lasting the given number of seconds
+    pub fn new(seconds: f32) -> Self {
+        Self(Timer::from_seconds(seconds, TimerMode::Once))
+    }
+}
+
+/// Insert this resource to count down before a game starts
+#[derive(Resource)]
+pub struct CountdownTimer(pub Timer);
+
+impl CountdownTimer {
+    /// Count down the given number of seconds
+    pub fn new(seconds: f32) -> Self {
+        Self(Timer::from_seconds(seconds, TimerMode::Once))
+    }
+
+    /// The number of whole seconds left, rounded up
+    pub fn seconds_left(&self) -> u32 {
+        self.0.remaining_secs().ceil() as u32
+    }
+}
+
+/// The text showing the seconds left in the countdown
+#[derive(Component)]
+pub struct CountdownText;
+
+/// The full-screen overlay used for fading between states
+#[derive(Component)]
+pub struct FadeOverlay;
+
+/// Times how long the loading screen is shown
+#[derive(Resource)]
+struct LoadingTimer(Timer);
+
+/// Set to true to pause the game when the window loses focus
+#[derive(Resource, Default)]
+pub struct AutoPauseOnBlur(pub bool);
+
+/// Set to true to show the current state in a corner
+#[derive(Resource, Default)]
+pub struct DebugOverlay(pub bool);
+
+/// The total number of seconds spent in each state
+#[derive(Resource, Default)]
+pub struct StateDurations(pub bevy::utils::HashMap<AppState, f32>);
+
+/// The camera scale the camera zooms to
+#[derive(Resource)]
+pub struct CameraZoom {
+    pub target: f32,
+}
+
+impl Default for CameraZoom {
+    fn default() -> Self {
+        Self {
+            target: IN_GAME_CAMERA_SCALE,
+        }
+    }
+}
+
+/// Set to false when a camera is already present
+#[derive(Resource)]
+pub struct SpawnCamera(pub bool);
+
+impl Default for SpawnCamera {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+/// How the camera clears the screen each frame
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct CameraConfig {
+    /// Set to false to draw over the previous frame
+    pub clear: bool,
+    /// The color to clear with, or 'None' to use the color of the state
+    pub color: Option<Color>,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            clear: true,
+            color: None,
+        }
+    }
+}
+
+/// Set to true to enable the debug shortcuts, such as F1 to go to the game.
+/// These shortcuts are only available in a debug build
+#[derive(Resource, Default)]
+pub struct DebugShortcuts(pub bool);
+
+/// Set to true to show the frames per second in a corner while in game
+#[derive(Resource, Default)]
+pub struct ShowFps(pub bool);
+
+/// The text in a corner that shows the frames per second
+#[derive(Component)]
+pub struct FpsText;
+
+/// The text in a corner that shows the current state
+#[derive(Component)]
+pub struct StateDebugText;
+
+/// The texts shown in the menu and in the game.
+/// Insert this resource to use custom texts instead of those of the 'Language'
+#[derive(Resource, Clone)]
+pub struct UiStrings {
+    pub menu: String,
+    pub game: String,
+}
+
+impl UiStrings {
+    /// The texts in the given language
+    pub fn from_language(language: Language) -> Self {
+        Self {
+            menu: text_for(language, TextKey::Menu),
+            game: text_for(language, TextKey::Game),
+        }
+    }
+}
+
+impl Default for UiStrings {
+    fn default() -> Self {
+        Self::from_language(Language::default())
+    }
+}
+
+/// The custom texts if there are any, otherwise the texts in the language
+fn get_ui_strings(language: Language, custom_strings: Option<&UiStrings>) -> UiStrings {
+    custom_strings
+        .cloned()
+        .unwrap_or_else(|| UiStrings::from_language(language))
+}
+
+/// The language of the texts
+#[derive(Resource, Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Language {
+    #[default]
+    English,
+    Dutch,
+}
+
+/// The texts that can be translated
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TextKey {
+    Menu,
+    Game,
+}
+
+/// Get a text in the given language
+pub fn text_for(language: Language, key: TextKey) -> String {
+    match (language, key) {
+        (Language::English, TextKey::Menu) => "Menu. Press space to start",
+        (Language::English, TextKey::Game) => "Game. Press escape to quit",
+        (Language::Dutch, TextKey::Menu) => "Menu. Druk op spatie om te beginnen",
+        (Language::Dutch, TextKey::Game) => "Spel. Druk op escape om te stoppen",
+    }
+    .to_string()
+}
+
+/// The number of state changes since the app started
+#[derive(Resource, Default)]
+pub struct TransitionCount(pub u32);
+
+/// The previous states, with the most recent last
+#[derive(Resource, Default)]
+pub struct StateHistory(pub Vec<AppState>);
+
+/// Is the next transition going back to a previous state?
+#[derive(Resource, Default)]
+struct NavigatingBack(bool);
+
+/// Send this event to start a game from the menu
+#[derive(Event)]
+pub struct StartGameRequested;
+
+/// Sent when a key is pressed that does nothing in the menu
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidKeyPressed(pub KeyCode);
+
+/// Send this event to end the game
+#[derive(Event)]
+pub struct GameOver;
+
+/// Send this event when the player dies, costing a life
+#[derive(Event)]
+pub struct PlayerDied;
+
+/// The number of lives left in the current game
+#[derive(Resource)]
+pub struct Lives(pub u32);
+
+impl Default for Lives {
+    fn default() -> Self {
+        Self(3)
+    }
+}
+
+/// Count the number of texts
+pub fn count_n_texts(app: &mut App) -> usize {
+    let mut query = app.world_mut().query::<&Text2d>();
+    query.iter(app.world()).len()
+}
+
+#[cfg(test)]
+fn get_pause_menu_option_texts(app: &mut App) -> Vec<String> {
+    let mut query = app.world_mut().query::<(&PauseMenuOption, &TextSpan)>();
+    let mut options: Vec<(usize, String)> = query
+        .iter(app.world())
+        .map(|(option, span)| (option.0, span.0.clone()))
+        .collect();
+    options.sort();
+    options.into_iter().map(|(_, text)| text).collect()
+}
+
+#[cfg(test)]
+fn get_menu_option_texts(app: &mut App) -> Vec<String> {
+    let mut query = app.world_mut().query::<(&MenuOption, &TextSpan)>();
+    let mut options: Vec<(usize, String)> = query
+        .iter(app.world())
+        .map(|(option, span)| (option.0, span.0.clone()))
+        .collect();
+    options.sort();
+    options.into_iter().map(|(_, text)| text).collect()
+}
+
+#[cfg(test)]
+fn get_visible_text(app: &mut App) -> String {
+    assert_eq!(count_visible_texts(app), 1);
+    let mut query = app.world_mut().query::<(&Text2d, &Visibility)>();
+    query
+        .iter(app.world())
+        .find(|(_, visibility)| **visibility != Visibility::Hidden)
+        .map(|(text, _)| text.0.clone())
+        .unwrap()
+}
+
+#[cfg(test)]
+fn get_state_debug_text(app: &mut App) -> String {
+    let mut query = app.world_mut().query_filtered::<&Text, With<StateDebugText>>();
+    query.single(app.world()).0.clone()
+}
+
+#[cfg(test)]
+fn count_fade_overlays(app: &mut App) -> usize {
+    let mut query = app.world_mut().query::<&FadeOverlay>();
+    query.iter(app.world()).len()
+}
+
+fn setup(
+    mut commands: Commands,
+    debug_overlay: Res<DebugOverlay>,
+    spawn_camera: Res<SpawnCamera>,
+) {
+    if spawn_camera.0 {
+        commands.spawn(Camera2d);
+    }
+    commands.insert_resource(Score::default());
+    if debug_overlay.0 {
+        // A UI text, so it is not counted as a Text2d
+        commands.spawn((
+            StateDebugText,
+            Text::new(""),
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(5.0),
+                left: Val::Px(5.0),
+                ..default()
+            },
+        ));
+    }
+}
+
+fn update_state_debug_text(
+    state: Res<State<AppState>>,
+    mut query: Query<&mut Text, With<StateDebugText>>,
+) {
+    for mut text in query.iter_mut() {
+        text.0 = format!("{:?}", state.get());
+    }
+}
+
+/// Hide the texts, so they need not be rebuilt when shown again
+fn hide_all_text(mut query: Query<&mut Visibility, With<Text2d>>) {
+    for mut visibility in query.iter_mut() {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+fn show_all_text(mut query: Query<&mut Visibility, With<Text2d>>) {
+    for mut visibility in query.iter_mut() {
+        *visibility = Visibility::Inherited;
+    }
+}
+
+
+/// Get the text of the entity with the given marker component,
+/// which can be a Text2d or a UI text
+pub fn get_text_by_marker<M: Component>(app: &mut App) -> Option<String> {
+    let mut query = app.world_mut().query_filtered::<&Text2d, With<M>>();
+    if let Some(text) = query.iter(app.world()).next() {
+        return Some(text.0.clone());
+    }
+    let mut query = app.world_mut().query_filtered::<&Text, With<M>>();
+    query.iter(app.world()).next().map(|text| text.0.clone())
+}
+
+/// Count the number of texts that are not hidden
+pub fn count_visible_texts(app: &mut App) -> usize {
+    let mut query = app.world_mut().query_filtered::<&Visibility, With<Text2d>>();
+    query
+        .iter(app.world())
+        .filter(|visibility| **visibility != Visibility::Hidden)
+        .count()
+}
+
+/// Get the text, assuming there is exactly one
+pub fn get_text(app: &mut App) -> String {
+    assert_eq!(count_n_texts(app), 1);
+    let mut query = app.world_mut().query::<&Text2d>();
+    query.single(app.world_mut()).0.clone()
+}
+
+/// Get the background color, as used by the camera
+pub fn get_clear_color(app: &mut App) -> Color {
+    app.world().resource::<ClearColor>().0
+}
+
+/// Get the first text, if there is any
+pub fn try_get_text(app: &mut App) -> Option<String> {
+    let mut query = app.world_mut().query::<&Text2d>();
+    query.iter(app.world()).next().map(|text| text.0.clone())
+}
+
+/// Get all texts, sorted, to compare against an expected snapshot
+pub fn dump_text_state(app: &mut App) -> Vec<String> {
+    let mut query = app.world_mut().query::<&Text2d>();
+    let mut texts: Vec<String> = query.iter(app.world()).map(|text| text.0.clone()).collect();
+    texts.sort();
+    texts
+}
+
+/// Update the app a number of times,
+/// asserting the text is the expected one after every frame
+pub fn assert_text_stable_for(app: &mut App, expected: &str, frames: usize) {
+    for frame in 0..frames {
+        app.update();
+        assert_eq!(get_text(app), expected, "text changed at frame {}", frame);
+    }
+}
+
+/// Press and release a key, as if it was tapped between two frames
+pub fn press_key(app: &mut App, key: KeyCode) {
+    hold_key(app, key);
+    release_key(app, key);
+}
+
+/// Get the logical key on a QWERTY keyboard,
+/// which is the logical key a binding to a physical key stands for
+fn get_logical_key(key: KeyCode) -> bevy::input::keyboard::Key {
+    use bevy::input::keyboard::{Key, NativeKey};
+    match key {
+        KeyCode::Space => Key::Space,
+        KeyCode::Escape => Key::Escape,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Tab => Key::Tab,
+        KeyCode::ArrowUp => Key::ArrowUp,
+        KeyCode::ArrowDown => Key::ArrowDown,
+        KeyCode::ArrowLeft => Key::ArrowLeft,
+        KeyCode::ArrowRight => Key::ArrowRight,
+        _ => {
+            // 'KeyA' becomes 'a', 'Digit1' becomes '1'
+            let name = format!("{:?}", key);
+            match name.strip_prefix("Key").or_else(|| name.strip_prefix("Digit")) {
+                Some(character) => Key::Character(character.to_lowercase().into()),
+                None => Key::Unidentified(NativeKey::Unidentified),
+            }
+        }
+    }
+}
+
+/// Press a key and keep it pressed
+pub fn hold_key(app: &mut App, key: KeyCode) {
+    send_key(app, key, bevy::input::ButtonState::Pressed);
+}
+
+/// Release a key that was held
+pub fn release_key(app: &mut App, key: KeyCode) {
+    send_key(app, key, bevy::input::ButtonState::Released);
+}
+
+fn send_key(app: &mut App, key: KeyCode, state: bevy::input::ButtonState) {
+    app.world_mut()
+        .send_event(bevy::input::keyboard::KeyboardInput {
+            key_code: key,
+            logical_key: get_logical_key(key),
+            state,
+            window: Entity::PLACEHOLDER,
+            repeat: false,
+        });
+}
+
+/// Send the recorded keys to the app, each as many frames apart as when recorded.
+/// The app is updated until the last recorded frame
+pub fn replay_input(app: &mut App, recording: &InputRecording) {
+    let (Some(first), Some(last)) = (recording.0.first(), recording.0.last()) else {
+        return;
+    };
+    for frame in first.0..=last.0 {
+        for (_, key, state) in recording.0.iter().filter(|(at, _, _)| *at == frame) {
+            send_key(app, *key, *state);
+        }
+        app.update();
+    }
+}
+
+/// Let each update take the given number of seconds, rounded to milliseconds,
+/// instead of the time that really passed
+pub fn set_frame_time(app: &mut App, seconds: f32) {
+    app.insert_resource(bevy::time::TimeUpdateStrategy::ManualDuration(
+        std::time::Duration::from_millis((seconds * 1000.0).round() as u64),
+    ));
+}
+
+/// Update the app a number of times
+pub fn run_frames(app: &mut App, n: usize) {
+    for _ in 0..n {
+        app.update();
+    }
+}
+
+/// Update the app until it is in the target state, for at most `max_frames` frames.
+/// Returns true if the target state was reached
+pub fn wait_for_state(app: &mut App, target: AppState, max_frames: usize) -> bool {
+    for _ in 0..max_frames {
+        if get_app_state(app) == target {
+            return true;
+        }
+        app.update();
+    }
+    get_app_state(app) == target
+}
+
+/// Get the number of texts left after the last state was exited
+pub fn get_leftover_text_count(app: &mut App) -> usize {
+    app.world().resource::<LeftoverTextCount>().0
+}
+
+/// Run the system only at the change from one state to another
+pub fn on_transition<M>(
+    app: &mut App,
+    from: AppState,
+    to: AppState,
+    system: impl IntoSystemConfigs<M>,
+) {
+    app.add_systems(
+        OnTransition {
+            exited: from,
+            entered: to,
+        },
+        system,
+    );
+}
+
+/// Request a change to the target state, without any input.
+/// The state changes at the next update.
+/// Requesting the current state does nothing
+pub fn request_state(app: &mut App, target: AppState) {
+    if get_app_state(app) != target {
+        app.world_mut().resource_mut::<NextState<AppState>>().set(target);
+    }
+}
+
+/// Assert that the app is in the given state,
+/// showing the actual state if it is not
+#[macro_export]
+macro_rules! assert_state {
+    ($app:expr, $state:expr) => {{
+        let actual = $crate::app::get_app_state(&mut $app);
+        let expected = $state;
+        assert_eq!(
+            actual, expected,
+            "expected the app to be in state {:?}, but it is in state {:?}",
+            expected, actual
+        );
+    }};
+}
+
+/// Get the current state of the app
+pub fn get_app_state(app: &mut App) -> AppState {
+    *app.world_mut().resource_mut::<State<AppState>>().get()
+}
+
+/// Get the keys to start and quit a game
+pub fn get_key_bindings(app: &mut App) -> KeyBindings {
+    KeyBindings::from(app.world().resource::<InputMap>())
+}
+
+/// Get the state that will be entered at the next state transition, if any
+pub fn get_pending_state(app: &mut App) -> Option<AppState> {
+    match app.world().resource::<NextState<AppState>>() {
+        NextState::Pending(state) => Some(*state),
+        NextState::Unchanged => None,
+    }
+}
+
+/// Get the state of the audio
+pub fn get_audio_state(app: &mut App) -> AudioState {
+    *app.world().resource::<State<AudioState>>().get()
+}
+
+/// Get the state of player two
+pub fn get_player_two_state(app: &mut App) -> PlayerTwoState {
+    *app.world().resource::<State<PlayerTwoState>>().get()
+}
+
+/// Get the state within the game, if in game
+pub fn get_in_game_state(app: &mut App) -> Option<InGameState> {
+    app.world().get_resource::<State<InGameState>>().map(|state| *state.get())
+}
+
+/// Count the entities that belong to a game
+pub fn count_gameplay_entities(app: &mut App) -> usize {
+    let mut query = app.world_mut().query_filtered::<Entity, With<GameplayEntity>>();
+    query.iter(app.world()).len()
+}
+
+/// Get the position of the player, assuming there is exactly one
+pub fn get_player_position(app: &mut App) -> Vec3 {
+    let mut query = app.world_mut().query_filtered::<&Transform, With<Player>>();
+    query.single(app.world()).translation
+}
+
+/// Is the game being played, also when paused?
+pub fn is_gameplay_active(app: &mut App) -> bool {
+    app.world().contains_resource::<State<IsGameplayActive>>()
+}
+
+/// Get the time played in the current or most recent game, in seconds
+pub fn get_game_time(app: &mut App) -> f32 {
+    app.world().resource::<GameClock>().0.elapsed_secs()
+}
+
+/// Get the score of the current or most recent game
+pub fn get_score(app: &mut App) -> u32 {
+    app.world().resource::<Score>().0
+}
+
+/// Get the exited and entered state of the state change in the last frame, if any
+pub fn transition_happened_this_frame(
+    app: &mut App,
+) -> Option<(Option<AppState>, Option<AppState>)> {
+    let events = app.world().resource::<Events<StateTransitionEvent<AppState>>>();
+    events
+        .iter_current_update_events()
+        .last()
+        .map(|transition| (transition.exited, transition.entered))
+}
+
+/// Count the entities that belong to a state, i.e. that are despawned when leaving it
+pub fn entities_for_state(app: &mut App, state: AppState) -> usize {
+    let mut query = app.world_mut().query::<&StateScoped<AppState>>();
+    query.iter(app.world()).filter(|scoped| scoped.0 == state).count()
+}
+
+/// Get every state change so far, with the frame number it happened in
+pub fn get_transition_log(app: &mut App) -> Vec<(u64, Option<AppState>, Option<AppState>)> {
+    app.world().resource::<TransitionLog>().0.clone()
+}
+
+/// Get how far the credits have scrolled up, or zero when not showing the credits
+pub fn get_credits_scroll_y(app: &mut App) -> f32 {
+    let mut query = app.world_mut().query_filtered::<&Transform, With<CreditsText>>();
+    query.iter(app.world()).next().map_or(0.0, |transform| transform.translation.y)
+}
+
+/// Get the scale of the camera, where a bigger scale shows more of the world
+pub fn get_camera_scale(app: &mut App) -> f32 {
+    let mut query = app
+        .world_mut()
+        .query_filtered::<&OrthographicProjection, With<Camera2d>>();
+    query.single(app.world()).scale
+}
+
+/// Restore the default 'CameraConfig', which is applied at the next update
+pub fn reset_camera(app: &mut App) {
+    app.insert_resource(CameraConfig::default());
+}
+
+/// Get the total number of seconds spent in the state
+pub fn time_in_state(app: &mut App, state: AppState) -> f32 {
+    app.world().resource::<StateDurations>().0.get(&state).copied().unwrap_or_default()
+}
+
+/// Is the game dimmed by the pause overlay?
+pub fn has_pause_overlay(app: &mut App) -> bool {
+    let mut query = app.world_mut().query::<&PauseOverlay>();
+    query.iter(app.world()).next().is_some()
+}
+
+/// Get the highest score of all games since the app started
+pub fn get_high_score(app: &mut App) -> u32 {
+    app.world().resource::<HighScore>().0
+}
+
+/// Get the number of lives left
+pub fn get_lives(app: &mut App) -> u32 {
+    app.world().resource::<Lives>().0
+}
+
+/// Get the number of state changes since the app started
+pub fn get_transition_count(app: &mut App) -> u32 {
+    app.world().resource::<TransitionCount>().0
+}
+
+#[cfg(test)]
+fn get_program_state(app: &mut App) -> AppState {
+    get_app_state(app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_app_has_text() {
+        let mut app = App::new();
+        app.update();
+        assert_eq!(count_n_texts(&mut app), 0);
+    }
+
+    #[test]
+    fn test_app_has_text() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(count_n_texts(&mut app), 1);
+    }
+
+    #[test]
+    fn test_app_has_menu_text() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(get_text(&mut app), "Menu. Press space to start");
+    }
+
+    #[test]
+    fn test_app_starts_at_menu() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_space_starts_game() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_escape_leaves_game() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_p_pauses_game() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Paused));
+        assert_eq!(get_visible_text(&mut app), "Paused. Press P to resume");
+    }
+
+    #[test]
+    fn test_p_resumes_game() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Paused));
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Playing));
+        assert_eq!(get_text(&mut app), "Game. Press escape to quit");
+    }
+
+    #[test]
+    fn test_game_over_event_ends_game() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        app.world_mut().send_event(GameOver);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::GameOver);
+        assert_eq!(get_text(&mut app), "Game over. Press R to restart or Q for menu");
+    }
+
+    #[test]
+    fn test_r_restarts_game_after_game_over() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        app.world_mut().send_event(GameOver);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::GameOver);
+        press_key(&mut app, KeyCode::KeyR);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert_eq!(get_text(&mut app), "Game. Press escape to quit");
+    }
+
+    #[test]
+    fn test_q_quits_to_menu_after_game_over() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        app.world_mut().send_event(GameOver);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::GameOver);
+        press_key(&mut app, KeyCode::KeyQ);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        assert_eq!(get_text(&mut app), "Menu. Press space to start");
+    }
+
+    #[test]
+    fn test_app_starts_with_zero_score() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(get_score(&mut app), 0);
+    }
+
+    #[test]
+    fn test_score_increases_in_game() {
+        let mut app = create_app();
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        let score_before = get_score(&mut app);
+        run_frames(&mut app, 3);
+        assert!(get_score(&mut app) > score_before);
+    }
+
+    #[test]
+    fn test_score_is_kept_in_menu_and_reset_in_new_game() {
+        let mut app = create_app();
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 5);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        let score_in_menu = get_score(&mut app);
+        assert!(score_in_menu > 1);
+        app.update();
+        assert_eq!(get_score(&mut app), score_in_menu);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert!(get_score(&mut app) < score_in_menu);
+    }
+
+    #[test]
+    fn test_custom_start_key_starts_game() {
+        let mut app =
+            create_app_with_input_map(InputMap::default().with(Action::Start, KeyCode::Enter));
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        press_key(&mut app, KeyCode::Enter);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_gamepad_south_starts_game() {
+        let mut app = create_app();
+        let gamepad = app.world_mut().spawn(Gamepad::default()).id();
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        app.world_mut()
+            .send_event(bevy::input::gamepad::RawGamepadEvent::Button(
+                bevy::input::gamepad::RawGamepadButtonChangedEvent::new(
+                    gamepad,
+                    GamepadButton::South,
+                    1.0,
+                ),
+            ));
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_gamepad_east_leaves_game() {
+        let mut app = create_app();
+        let gamepad = app.world_mut().spawn(Gamepad::default()).id();
+        app.update();
+        app.world_mut()
+            .send_event(bevy::input::gamepad::RawGamepadEvent::Button(
+                bevy::input::gamepad::RawGamepadButtonChangedEvent::new(
+                    gamepad,
+                    GamepadButton::South,
+                    1.0,
+                ),
+            ));
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        app.world_mut()
+            .send_event(bevy::input::gamepad::RawGamepadEvent::Button(
+                bevy::input::gamepad::RawGamepadButtonChangedEvent::new(
+                    gamepad,
+                    GamepadButton::East,
+                    1.0,
+                ),
+            ));
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_s_opens_settings_and_escape_goes_back() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::KeyS);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Settings);
+        assert_eq!(get_text(&mut app), "Settings. Press escape to go back");
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        assert_eq!(get_text(&mut app), "Menu. Press space to start");
+    }
+
+    #[test]
+    fn test_app_can_start_in_game() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert_eq!(get_text(&mut app), "Game. Press escape to quit");
+    }
+
+    #[test]
+    fn test_input_during_fade_is_ignored() {
+        let mut app = create_app_with_fade(0.5);
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(count_fade_overlays(&mut app), 1);
+        press_key(&mut app, KeyCode::KeyS);
+        press_key(&mut app, KeyCode::Escape);
+        for _ in 0..20 {
+            app.update();
+            assert!(matches!(get_program_state(&mut app), AppState::Menu | AppState::InGame));
+        }
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_fade_is_dropped_when_state_changes_during_fade_out() {
+        let mut app = create_app_with_fade(0.5);
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        app.world_mut()
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::Settings);
+        run_frames(&mut app, 20);
+        assert_eq!(get_program_state(&mut app), AppState::Settings);
+        assert_eq!(count_fade_overlays(&mut app), 0);
+    }
+
+    #[test]
+    fn test_fade_delays_state_change() {
+        let mut app = create_app_with_fade(0.5);
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        assert_eq!(count_fade_overlays(&mut app), 0);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        assert_eq!(count_fade_overlays(&mut app), 1);
+        run_frames(&mut app, 20);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert_eq!(count_fade_overlays(&mut app), 0);
+    }
+
+    #[test]
+    fn test_transitions_are_logged_with_timestamp() {
+        let mut app = create_app();
+        app.init_resource::<TransitionLogs>();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        let logs = &app.world().resource::<TransitionLogs>().0;
+        let last = logs.last().unwrap();
+        assert!(last.starts_with("AppState transition at "));
+        assert!(last.ends_with("s: Menu => InGame"));
+    }
+
+    #[test]
+    fn test_app_starts_with_zero_transitions() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(get_transition_count(&mut app), 0);
+    }
+
+    #[test]
+    fn test_transitions_are_counted() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        assert_eq!(get_transition_count(&mut app), 2);
+    }
+
+    #[test]
+    fn test_app_uses_custom_strings() {
+        let mut app = create_app_with_strings(UiStrings {
+            menu: "Welcome".to_string(),
+            game: "Playing".to_string(),
+        });
+        app.update();
+        assert_eq!(get_text(&mut app), "Welcome");
+    }
+
+    #[test]
+    fn test_loading_goes_to_menu() {
+        let mut app = create_app_in_state(AppState::default());
+        set_frame_time(&mut app, 3.0);
+        app.world_mut()
+            .resource_mut::<Time<Virtual>>()
+            .set_max_delta(std::time::Duration::from_secs(10));
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::Loading);
+        assert_eq!(get_text(&mut app), "Loading...");
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        assert_eq!(get_text(&mut app), "Menu. Press space to start");
+    }
+
+    #[test]
+    fn test_in_game_state_only_exists_in_game() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(get_in_game_state(&mut app), None);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Playing));
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        assert_eq!(get_in_game_state(&mut app), None);
+    }
+
+    #[test]
+    fn test_pausing_stops_score() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Paused));
+        let score_when_paused = get_score(&mut app);
+        run_frames(&mut app, 3);
+        assert_eq!(get_score(&mut app), score_when_paused);
+    }
+
+    #[test]
+    fn test_menu_starts_with_start_selected() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(
+            get_menu_option_texts(&mut app),
+            vec!["\n> Start".to_string(), "\n  Quit".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_enter_on_start_starts_game() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Enter);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert!(app.should_exit().is_none());
+    }
+
+    #[test]
+    fn test_enter_on_quit_exits() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::ArrowDown);
+        app.update();
+        assert_eq!(app.world().resource::<MenuSelection>().0, 1);
+        assert_eq!(
+            get_menu_option_texts(&mut app),
+            vec!["\n  Start".to_string(), "\n> Quit".to_string()]
+        );
+        press_key(&mut app, KeyCode::Enter);
+        app.update();
+        assert_eq!(app.should_exit(), Some(AppExit::Success));
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_game_over_saves_high_score() {
+        let path = std::env::temp_dir().join(format!(
+            "bevy_tdd_book_use_game_state_game_over_{}.txt",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let mut app = create_app_in_state(AppState::InGame);
+        app.insert_resource(HighScorePath(path.clone()));
+        set_frame_time(&mut app, 0.1);
+        run_frames(&mut app, 2);
+        app.world_mut().send_event(GameOver);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::GameOver);
+        let score = get_score(&mut app);
+        assert!(score > 0);
+        assert_eq!(crate::persistence::load_high_score(&path), score);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_player_starts_at_origin() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        assert_eq!(get_player_position(&mut app), Vec3::ZERO);
+    }
+
+    #[test]
+    fn test_d_moves_player_right() {
+        let mut app = create_app_in_state(AppState::InGame);
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        hold_key(&mut app, KeyCode::KeyD);
+        run_frames(&mut app, 3);
+        assert!(get_player_position(&mut app).x > 0.0);
+        assert_eq!(get_player_position(&mut app).y, 0.0);
+    }
+
+    #[test]
+    fn test_backspace_goes_back() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::KeyS);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Settings);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        press_key(&mut app, KeyCode::Backspace);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Settings);
+        press_key(&mut app, KeyCode::Backspace);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        assert!(app.world().resource::<StateHistory>().0.is_empty());
+    }
+
+    #[test]
+    fn test_backspace_without_history_does_nothing() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Backspace);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_high_score_wins_game() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        app.world_mut().resource_mut::<Score>().0 = 100;
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Victory);
+        assert_eq!(get_text(&mut app), "You win! Press space for menu");
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_hold_to_start_needs_a_long_hold() {
+        let mut app = create_app();
+        app.insert_resource(HoldToStart {
+            timer: Timer::from_seconds(1.0, TimerMode::Once),
+            enabled: true,
+        });
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        hold_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 5);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        run_frames(&mut app, 10);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_hold_to_start_resets_on_release() {
+        let mut app = create_app();
+        app.insert_resource(HoldToStart {
+            timer: Timer::from_seconds(1.0, TimerMode::Once),
+            enabled: true,
+        });
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        hold_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 7);
+        release_key(&mut app, KeyCode::Space);
+        app.update();
+        hold_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 7);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_game_clock_stops_while_paused() {
+        let mut app = create_app_in_state(AppState::InGame);
+        set_frame_time(&mut app, 0.1);
+        run_frames(&mut app, 3);
+        let time_before_pause = get_game_time(&mut app);
+        assert!(time_before_pause > 0.0);
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Paused));
+        let time_when_paused = get_game_time(&mut app);
+        run_frames(&mut app, 5);
+        assert_eq!(get_game_time(&mut app), time_when_paused);
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 5);
+        assert!(get_game_time(&mut app) > time_when_paused);
+    }
+
+    #[test]
+    fn test_start_game_request_starts_game() {
+        let mut app = create_app();
+        app.update();
+        app.world_mut().send_event(StartGameRequested);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_text_for_english_menu() {
+        assert_eq!(
+            text_for(Language::English, TextKey::Menu),
+            "Menu. Press space to start"
+        );
+    }
+
+    #[test]
+    fn test_app_in_dutch_has_dutch_texts() {
+        let mut app = create_app_with_language(Language::Dutch);
+        app.update();
+        assert_eq!(get_text(&mut app), text_for(Language::Dutch, TextKey::Menu));
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_text(&mut app), text_for(Language::Dutch, TextKey::Game));
+    }
+
+    #[test]
+    fn test_changing_language_changes_texts() {
+        let mut app = create_app();
+        app.update();
+        app.insert_resource(Language::Dutch);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_text(&mut app), text_for(Language::Dutch, TextKey::Game));
+    }
+
+    #[test]
+    fn test_custom_strings_take_precedence_over_language() {
+        let mut app = create_app_with_language(Language::Dutch);
+        app.insert_resource(UiStrings {
+            menu: "Welcome".to_string(),
+            game: "Playing".to_string(),
+        });
+        app.update();
+        assert_eq!(get_text(&mut app), "Welcome");
+    }
+
+    #[test]
+    fn test_left_click_starts_game() {
+        let mut app = create_app();
+        app.update();
+        app.world_mut()
+            .send_event(bevy::input::mouse::MouseButtonInput {
+                button: MouseButton::Left,
+                state: bevy::input::ButtonState::Pressed,
+                window: Entity::PLACEHOLDER,
+            });
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_gameplay_is_active_in_game_and_when_paused() {
+        let mut app = create_app();
+        app.update();
+        assert!(!is_gameplay_active(&mut app));
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert!(is_gameplay_active(&mut app));
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Paused));
+        assert!(is_gameplay_active(&mut app));
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert!(!is_gameplay_active(&mut app));
+    }
+
+    #[test]
+    fn test_new_game_has_only_fresh_gameplay_entities() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(count_gameplay_entities(&mut app), 0);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(count_gameplay_entities(&mut app), 1);
+        app.world_mut().spawn(GameplayEntity);
+        app.world_mut().spawn(GameplayEntity);
+        assert_eq!(count_gameplay_entities(&mut app), 3);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(count_gameplay_entities(&mut app), 0);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(count_gameplay_entities(&mut app), 1);
+    }
+
+    #[test]
+    fn test_new_game_removes_lingering_gameplay_entities() {
+        let mut app = create_app();
+        app.update();
+        app.world_mut().spawn(GameplayEntity);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(count_gameplay_entities(&mut app), 1);
+    }
+
+    #[test]
+    fn test_try_get_text_in_empty_app() {
+        let mut app = App::new();
+        app.update();
+        assert_eq!(try_get_text(&mut app), None);
+    }
+
+    #[test]
+    fn test_try_get_text_in_menu() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(
+            try_get_text(&mut app),
+            Some("Menu. Press space to start".to_string())
+        );
+    }
+
+    #[test]
+    fn test_holding_down_repeats_after_delay() {
+        let mut app = create_app();
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        hold_key(&mut app, KeyCode::ArrowDown);
+        app.update();
+        assert_eq!(app.world().resource::<MenuSelection>().0, 1);
+        run_frames(&mut app, 4);
+        assert_eq!(app.world().resource::<MenuSelection>().0, 1);
+        app.update();
+        assert_eq!(app.world().resource::<MenuSelection>().0, 0);
+        app.update();
+        assert_eq!(app.world().resource::<MenuSelection>().0, 1);
+        app.update();
+        assert_eq!(app.world().resource::<MenuSelection>().0, 0);
+        release_key(&mut app, KeyCode::ArrowDown);
+        run_frames(&mut app, 3);
+        assert_eq!(app.world().resource::<MenuSelection>().0, 0);
+    }
+
+    #[test]
+    fn test_escape_in_menu_asks_to_quit() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::ConfirmQuit);
+        assert_eq!(get_text(&mut app), "Quit? Y/N");
+    }
+
+    #[test]
+    fn test_y_confirms_quit() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert!(app.should_exit().is_none());
+        press_key(&mut app, KeyCode::KeyY);
+        app.update();
+        assert_eq!(app.should_exit(), Some(AppExit::Success));
+    }
+
+    #[test]
+    fn test_n_cancels_quit() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::KeyN);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        assert!(app.should_exit().is_none());
+    }
+
+    #[test]
+    fn test_transition_is_logged_in_the_frame_it_happens() {
+        let mut app = create_app();
+        app.init_resource::<TransitionLogs>();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        assert!(app.world().resource::<TransitionLogs>().0.last().unwrap().ends_with("=> Menu"));
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert!(app.world().resource::<TransitionLogs>().0.last().unwrap().ends_with("=> InGame"));
+    }
+
+    #[test]
+    fn test_clear_color_changes_per_state() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(get_clear_color(&mut app), MENU_CLEAR_COLOR);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_clear_color(&mut app), IN_GAME_CLEAR_COLOR);
+    }
+
+    #[test]
+    fn test_debug_overlay_shows_state() {
+        let mut app = create_app();
+        app.insert_resource(DebugOverlay(true));
+        app.update();
+        assert_eq!(get_state_debug_text(&mut app), "Menu");
+        assert_eq!(count_n_texts(&mut app), 1);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_state_debug_text(&mut app), "InGame");
+    }
+
+    #[test]
+    fn test_m_toggles_audio_in_menu() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(get_audio_state(&mut app), AudioState::On);
+        press_key(&mut app, KeyCode::KeyM);
+        run_frames(&mut app, 2);
+        assert_eq!(get_audio_state(&mut app), AudioState::Muted);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        press_key(&mut app, KeyCode::KeyM);
+        run_frames(&mut app, 2);
+        assert_eq!(get_audio_state(&mut app), AudioState::On);
+    }
+
+    #[test]
+    fn test_m_toggles_audio_in_game() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        press_key(&mut app, KeyCode::KeyM);
+        run_frames(&mut app, 2);
+        assert_eq!(get_audio_state(&mut app), AudioState::Muted);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_rapid_transitions_no_leak() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(count_n_texts(&mut app), 1);
+        for i in 0..500 {
+            let key = if i % 2 == 0 { KeyCode::Space } else { KeyCode::Escape };
+            press_key(&mut app, key);
+            for _ in 0..2 {
+                app.update();
+                assert_eq!(count_n_texts(&mut app), 1);
+                assert!(count_gameplay_entities(&mut app) <= 1);
+            }
+            let expected_state = if i % 2 == 0 { AppState::InGame } else { AppState::Menu };
+            assert_eq!(get_program_state(&mut app), expected_state);
+        }
+    }
+
+    #[derive(Resource, Default)]
+    struct TextsOnEnteringGame(Option<usize>);
+
+    #[test]
+    fn test_state_scoped_texts_are_removed_on_exit() {
+        let mut app = create_app();
+        app.init_resource::<TextsOnEnteringGame>();
+        app.add_systems(
+            OnEnter(AppState::InGame),
+            |query: Query<&Text2d>, mut texts: ResMut<TextsOnEnteringGame>| {
+                texts.0 = Some(query.iter().len());
+            },
+        );
+        app.update();
+        assert_eq!(count_n_texts(&mut app), 1);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(app.world().resource::<TextsOnEnteringGame>().0, Some(0));
+        assert_eq!(count_n_texts(&mut app), 1);
+        assert_eq!(get_text(&mut app), "Game. Press escape to quit");
+    }
+
+    #[test]
+    fn test_score_rate_is_per_second() {
+        let mut app = create_app_with_score_rate(5.0);
+        app.insert_state(AppState::InGame);
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        assert_eq!(get_score(&mut app), 0);
+        run_frames(&mut app, 10);
+        assert_eq!(get_score(&mut app), 5);
+    }
+
+    #[test]
+    fn test_pause_hides_game_text() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        assert_eq!(count_visible_texts(&mut app), 1);
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert_eq!(count_n_texts(&mut app), 2);
+        assert_eq!(count_visible_texts(&mut app), 1);
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert_eq!(count_n_texts(&mut app), 1);
+        assert_eq!(count_visible_texts(&mut app), 1);
+        assert_eq!(get_text(&mut app), "Game. Press escape to quit");
+    }
+
+    #[test]
+    fn test_score_text_shows_score() {
+        let mut app = create_app_in_state(AppState::InGame);
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        assert_eq!(get_text_by_marker::<ScoreText>(&mut app), Some("Score: 0".to_string()));
+        run_frames(&mut app, 5);
+        let score = get_score(&mut app);
+        assert!(score > 0);
+        assert_eq!(get_text_by_marker::<ScoreText>(&mut app), Some(format!("Score: {}", score)));
+        assert_eq!(count_n_texts(&mut app), 1);
+    }
+
+    #[test]
+    fn test_score_text_is_gone_in_menu() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(get_text_by_marker::<ScoreText>(&mut app), None);
+    }
+
+    #[test]
+    fn test_registered_state_names() {
+        let app = create_app();
+        let names = registered_state_names(&app);
+        assert!(names.contains(&"AppState"));
+        assert!(names.contains(&"InGameState"));
+        assert!(names.contains(&"IsGameplayActive"));
+        assert!(names.contains(&"AudioState"));
+        assert!(names.contains(&"PlayerTwoState"));
+    }
+
+    #[test]
+    fn test_bare_app_has_no_registered_states() {
+        let _app = create_app();
+        assert!(registered_state_names(&App::new()).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_key_in_menu_is_reported() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::KeyZ);
+        app.update();
+        let events = app.world().resource::<Events<InvalidKeyPressed>>();
+        let invalid_keys: Vec<InvalidKeyPressed> =
+            events.iter_current_update_events().copied().collect();
+        assert_eq!(invalid_keys, vec![InvalidKeyPressed(KeyCode::KeyZ)]);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_valid_key_in_menu_is_not_reported() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::KeyS);
+        app.update();
+        let events = app.world().resource::<Events<InvalidKeyPressed>>();
+        assert_eq!(events.iter_current_update_events().count(), 0);
+    }
+
+    fn get_random_numbers(seed: u64) -> Vec<u32> {
+        use rand::Rng;
+        let mut app = create_app_with_seed(seed);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        let mut rng = app.world_mut().resource_mut::<GameRng>();
+        (0..5).map(|_| rng.0.gen()).collect()
+    }
+
+    #[test]
+    fn test_same_seed_gives_same_random_numbers() {
+        assert_eq!(get_random_numbers(42), get_random_numbers(42));
+    }
+
+    #[test]
+    fn test_different_seeds_give_different_random_numbers() {
+        assert_ne!(get_random_numbers(1), get_random_numbers(2));
+    }
+
+    #[test]
+    fn test_focus_loss_pauses_game() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.insert_resource(AutoPauseOnBlur(true));
+        app.update();
+        app.world_mut().send_event(WindowFocused {
+            window: Entity::PLACEHOLDER,
+            focused: false,
+        });
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Paused));
+    }
+
+    #[test]
+    fn test_focus_loss_does_not_pause_when_disabled() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        app.world_mut().send_event(WindowFocused {
+            window: Entity::PLACEHOLDER,
+            focused: false,
+        });
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Playing));
+    }
+
+    #[test]
+    fn test_remapped_start_action_fires_on_new_key() {
+        let mut app =
+            create_app_with_input_map(InputMap::default().with(Action::Start, KeyCode::KeyG));
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        press_key(&mut app, KeyCode::KeyG);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_remapped_pause_action_fires_on_new_key() {
+        let mut app =
+            create_app_with_input_map(InputMap::default().with(Action::Pause, KeyCode::Tab));
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Tab);
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Paused));
+    }
+
+    #[test]
+    fn test_action_just_pressed() {
+        let mut input = ButtonInput::<KeyCode>::default();
+        let map = InputMap::default().with(Action::Start, KeyCode::KeyG);
+        input.press(KeyCode::KeyG);
+        assert!(action_just_pressed(&input, &map, Action::Start));
+        assert!(!action_just_pressed(&input, &map, Action::Quit));
+    }
+
+    #[test]
+    fn test_dump_text_state_in_menu() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(dump_text_state(&mut app), vec!["Menu. Press space to start"]);
+    }
+
+    #[test]
+    fn test_dump_text_state_in_game() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(dump_text_state(&mut app), vec![get_text(&mut app)]);
+    }
+
+    #[test]
+    fn test_wait_for_state_reaches_in_game() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        assert!(wait_for_state(&mut app, AppState::InGame, 5));
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_wait_for_state_gives_up() {
+        let mut app = create_app();
+        app.update();
+        assert!(!wait_for_state(&mut app, AppState::InGame, 5));
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_no_leftover_texts_after_exiting_menu() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert_eq!(get_leftover_text_count(&mut app), 0);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_untracked_text_is_detected_as_leftover() {
+        let mut app = create_app();
+        app.update();
+        app.world_mut().spawn(Text2d::new("Untracked"));
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert_eq!(get_leftover_text_count(&mut app), 1);
+    }
+
+    #[test]
+    fn test_countdown_before_game_starts() {
+        let mut app = create_app_with_countdown(3.0);
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Countdown);
+        assert_eq!(get_text(&mut app), "3");
+        run_frames(&mut app, 11);
+        assert_eq!(get_text(&mut app), "2");
+        run_frames(&mut app, 10);
+        assert_eq!(get_text(&mut app), "1");
+        assert!(wait_for_state(&mut app, AppState::InGame, 20));
+    }
+
+    #[test]
+    fn test_escape_cancels_countdown() {
+        let mut app = create_app_with_countdown(3.0);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Countdown);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_window_close_in_game_goes_to_game_over() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        app.world_mut().send_event(WindowCloseRequested {
+            window: Entity::PLACEHOLDER,
+        });
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::GameOver);
+        assert!(app.should_exit().is_none());
+    }
+
+    #[test]
+    fn test_window_close_in_menu_exits() {
+        let mut app = create_app();
+        app.update();
+        app.world_mut().send_event(WindowCloseRequested {
+            window: Entity::PLACEHOLDER,
+        });
+        app.update();
+        assert_eq!(app.should_exit(), Some(AppExit::Success));
+    }
+
+    #[test]
+    fn test_menu_and_game_texts_are_centered() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(count_n_texts(&mut app), 1);
+        let mut query = app.world_mut().query::<(&Text2d, &TextLayout)>();
+        assert_eq!(query.single(app.world()).1.justify, JustifyText::Center);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(count_n_texts(&mut app), 1);
+        let mut query = app.world_mut().query::<(&Text2d, &TextLayout)>();
+        assert_eq!(query.single(app.world()).1.justify, JustifyText::Center);
+    }
+
+    #[test]
+    fn test_each_state_shows_its_text() {
+        for state in all_app_states() {
+            let mut app = create_app_in_state(state);
+            app.insert_resource(CountdownTimer::new(3.0));
+            app.update();
+            let expected = match state {
+                AppState::Loading => "Loading...".to_string(),
+                AppState::Menu => text_for(Language::English, TextKey::Menu),
+                AppState::InGame => text_for(Language::English, TextKey::Game),
+                AppState::GameOver => "Game over. Press R to restart or Q for menu".to_string(),
+                AppState::Settings => "Settings. Press escape to go back".to_string(),
+                AppState::Victory => "You win! Press space for menu".to_string(),
+                AppState::ConfirmQuit => "Quit? Y/N".to_string(),
+                AppState::Countdown => "3".to_string(),
+                AppState::Credits => CREDITS_TEXT.to_string(),
+            };
+            assert_eq!(get_text(&mut app), expected, "in state {:?}", state);
+        }
+    }
+
+    #[test]
+    fn test_reaching_win_target_is_victory() {
+        let mut app = create_app_with_win_target(5);
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert!(wait_for_state(&mut app, AppState::Victory, 10));
+        assert!(get_score(&mut app) >= 5);
+    }
+
+    #[test]
+    fn test_losing_all_lives_is_game_over() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        assert_eq!(get_lives(&mut app), 3);
+        for expected_lives in [2, 1, 0] {
+            app.world_mut().send_event(PlayerDied);
+            app.update();
+            assert_eq!(get_lives(&mut app), expected_lives);
+        }
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::GameOver);
+    }
+
+    #[test]
+    fn test_lives_are_reset_in_new_game() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        app.world_mut().send_event(PlayerDied);
+        app.update();
+        assert_eq!(get_lives(&mut app), 2);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert_eq!(get_lives(&mut app), 3);
+    }
+
+    #[test]
+    fn test_pause_menu_shows_options() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Paused));
+        assert_eq!(
+            get_pause_menu_option_texts(&mut app),
+            vec!["\n> Resume".to_string(), "\n  Quit to menu".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_pause_menu_quit_to_menu() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::ArrowDown);
+        app.update();
+        assert_eq!(
+            get_pause_menu_option_texts(&mut app),
+            vec!["\n  Resume".to_string(), "\n> Quit to menu".to_string()]
+        );
+        press_key(&mut app, KeyCode::Enter);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        assert_eq!(get_pause_menu_option_texts(&mut app).len(), 0);
+    }
+
+    #[test]
+    fn test_pause_menu_resume() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Enter);
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Playing));
+    }
+
+    #[test]
+    fn test_transition_happened_this_frame() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        app.update();
+        assert_eq!(transition_happened_this_frame(&mut app), None);
+        app.update();
+        assert_eq!(
+            transition_happened_this_frame(&mut app),
+            Some((Some(AppState::Menu), Some(AppState::InGame)))
+        );
+        app.update();
+        assert_eq!(transition_happened_this_frame(&mut app), None);
+    }
+
+    #[test]
+    fn test_press_and_release_in_same_frame_starts_game() {
+        let mut app = create_app();
+        app.update();
+        send_key(&mut app, KeyCode::Space, bevy::input::ButtonState::Pressed);
+        send_key(&mut app, KeyCode::Space, bevy::input::ButtonState::Released);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_start_key_is_taken_from_input_buffer() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        app.update();
+        assert!(app.world().resource::<InputBuffer>().0.is_empty());
+    }
+
+    #[test]
+    fn test_fps_text_only_in_game() {
+        let mut app = create_app();
+        app.insert_resource(ShowFps(true));
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        assert_eq!(get_text_by_marker::<FpsText>(&mut app), None);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_text_by_marker::<FpsText>(&mut app), Some("FPS: 10".to_string()));
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_text_by_marker::<FpsText>(&mut app), None);
+    }
+
+    #[test]
+    fn test_no_fps_text_by_default() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_text_by_marker::<FpsText>(&mut app), None);
+    }
+
+    #[test]
+    fn test_restart_keeps_high_score_only() {
+        let mut app = create_app_with_score_rate(0.0);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        app.world_mut().resource_mut::<Score>().0 = 50;
+        app.world_mut().send_event(GameOver);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::GameOver);
+        assert_eq!(get_high_score(&mut app), 50);
+        press_key(&mut app, KeyCode::KeyR);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert_eq!(get_score(&mut app), 0);
+        assert_eq!(get_high_score(&mut app), 50);
+    }
+
+    #[test]
+    fn test_lower_score_does_not_lower_high_score() {
+        let mut app = create_app_with_score_rate(0.0);
+        app.insert_resource(HighScore(80));
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        app.world_mut().resource_mut::<Score>().0 = 50;
+        app.world_mut().send_event(GameOver);
+        run_frames(&mut app, 2);
+        assert_eq!(get_high_score(&mut app), 80);
+    }
+
+    #[test]
+    fn test_timed_game_ends_in_game_over() {
+        let mut app = create_app_timed(1.0);
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        run_frames(&mut app, 5);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert!(wait_for_state(&mut app, AppState::GameOver, 10));
+    }
+
+    #[test]
+    fn test_untimed_game_does_not_end() {
+        let mut app = create_app();
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        assert!(!wait_for_state(&mut app, AppState::GameOver, 20));
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_entities_for_state_are_cleaned_up() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(entities_for_state(&mut app, AppState::InGame), 0);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert!(entities_for_state(&mut app, AppState::InGame) > 0);
+        assert_eq!(entities_for_state(&mut app, AppState::Menu), 0);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(entities_for_state(&mut app, AppState::InGame), 0);
+        assert!(entities_for_state(&mut app, AppState::Menu) > 0);
+    }
+
+    #[test]
+    fn test_quick_double_escape_in_menu_exits() {
+        let mut app = create_app();
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Escape);
+        app.update();
+        assert!(app.should_exit().is_none());
+        press_key(&mut app, KeyCode::Escape);
+        app.update();
+        assert_eq!(app.should_exit(), Some(AppExit::Success));
+    }
+
+    #[test]
+    fn test_slow_double_escape_in_menu_does_not_exit() {
+        let mut app = create_app();
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 5);
+        press_key(&mut app, KeyCode::Escape);
+        app.update();
+        assert!(app.should_exit().is_none());
+        assert_eq!(get_program_state(&mut app), AppState::ConfirmQuit);
+    }
+
+    fn count_cameras(app: &mut App) -> usize {
+        let mut query = app.world_mut().query::<&Camera2d>();
+        query.iter(app.world()).len()
+    }
+
+    #[test]
+    fn test_app_can_have_a_camera() {
+        let mut app = create_app();
+        app.insert_resource(SpawnCamera(true));
+        app.update();
+        assert_eq!(count_cameras(&mut app), 1);
+    }
+
+    #[test]
+    fn test_camera_is_spawned_by_default() {
+        let mut app = create_app();
+        app.insert_resource(SpawnCamera::default());
+        app.update();
+        assert_eq!(count_cameras(&mut app), 1);
+    }
+
+    #[test]
+    fn test_app_without_camera() {
+        let mut app = create_app_without_camera();
+        app.update();
+        assert_eq!(count_cameras(&mut app), 0);
+    }
+
+    #[test]
+    fn test_p_toggles_pause_both_ways() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Paused));
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Playing));
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert_eq!(get_in_game_state(&mut app), Some(InGameState::Paused));
+    }
+
+    #[test]
+    fn test_p_does_nothing_in_menu() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        assert_eq!(get_in_game_state(&mut app), None);
+    }
+
+    #[test]
+    fn test_transition_log_has_increasing_frame_numbers() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        let log = get_transition_log(&mut app);
+        let changes: Vec<(Option<AppState>, Option<AppState>)> =
+            log.iter().skip(1).map(|(_, exited, entered)| (*exited, *entered)).collect();
+        assert_eq!(
+            changes,
+            vec![
+                (Some(AppState::Menu), Some(AppState::InGame)),
+                (Some(AppState::InGame), Some(AppState::Menu)),
+            ]
+        );
+        assert!(log[0].0 < log[1].0);
+        assert!(log[1].0 < log[2].0);
+    }
+
+    #[cfg(debug_assertions)]
+    #[derive(Resource, Default)]
+    struct GameTextSpawnCount(usize);
+
+    #[cfg(debug_assertions)]
+    fn count_game_text_spawns(mut count: ResMut<GameTextSpawnCount>) {
+        count.0 += 1;
+    }
+
+    /// F1 requests the game from any state, also from within the game,
+    /// so it only leaves the game alone thanks to the current-state guard
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_start_while_in_game_does_not_respawn() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.insert_resource(DebugShortcuts(true));
+        app.init_resource::<GameTextSpawnCount>();
+        app.add_systems(OnEnter(AppState::InGame), count_game_text_spawns.after(add_game_text));
+        app.update();
+        assert_eq!(app.world().resource::<GameTextSpawnCount>().0, 1);
+        press_key(&mut app, KeyCode::F1);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert_eq!(app.world().resource::<GameTextSpawnCount>().0, 1);
+        assert_eq!(count_n_texts(&mut app), 1);
+    }
+
+    fn perform_menu_action_in(app: &mut App, action: MenuAction) {
+        use bevy::ecs::system::RunSystemOnce;
+        app.world_mut()
+            .run_system_once(
+                move |mut start_requests: EventWriter<StartGameRequested>,
+                      mut exit: EventWriter<AppExit>| {
+                    perform_menu_action(action, &mut start_requests, &mut exit);
+                },
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_menu_action_start_starts_game() {
+        let mut app = create_app();
+        app.update();
+        perform_menu_action_in(&mut app, MenuAction::Start);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert!(app.should_exit().is_none());
+    }
+
+    #[test]
+    fn test_menu_action_start_uses_countdown() {
+        let mut app = create_app_with_countdown(3.0);
+        app.update();
+        perform_menu_action_in(&mut app, MenuAction::Start);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Countdown);
+    }
+
+    #[test]
+    fn test_menu_action_quit_exits() {
+        let mut app = create_app();
+        app.update();
+        perform_menu_action_in(&mut app, MenuAction::Quit);
+        app.update();
+        assert_eq!(app.should_exit(), Some(AppExit::Success));
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_save_is_restored() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        app.world_mut().resource_mut::<Score>().0 = 42;
+        app.world_mut().resource_mut::<Lives>().0 = 2;
+        let save = capture_save(app.world());
+        app.world_mut().resource_mut::<Score>().0 = 7;
+        app.world_mut().resource_mut::<Lives>().0 = 1;
+        apply_save(app.world_mut(), &save);
+        assert_eq!(get_score(&mut app), 42);
+        assert_eq!(get_lives(&mut app), 2);
+        assert_eq!(capture_save(app.world()), save);
+    }
+
+    #[test]
+    fn test_quick_save_and_load() {
+        let mut app = create_app_with_score_rate(0.0);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        app.world_mut().resource_mut::<Score>().0 = 42;
+        press_key(&mut app, KeyCode::F5);
+        app.update();
+        app.world_mut().resource_mut::<Score>().0 = 7;
+        press_key(&mut app, KeyCode::F9);
+        app.update();
+        assert_eq!(get_score(&mut app), 42);
+    }
+
+    fn get_window_title(app: &mut App) -> String {
+        let mut query = app.world_mut().query_filtered::<&Window, With<PrimaryWindow>>();
+        query.single(app.world()).title.clone()
+    }
+
+    #[test]
+    fn test_window_title_shows_state() {
+        let mut app = create_app();
+        app.world_mut().spawn((Window::default(), PrimaryWindow));
+        app.update();
+        assert!(get_window_title(&mut app).contains("Menu"));
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert!(get_window_title(&mut app).contains("InGame"));
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_f1_jumps_to_game_with_debug_shortcuts() {
+        let mut app = create_app();
+        app.insert_resource(DebugShortcuts(true));
+        app.update();
+        press_key(&mut app, KeyCode::F1);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_f1_does_nothing_without_debug_shortcuts() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::F1);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_assert_state() {
+        let mut app = create_app();
+        app.update();
+        crate::assert_state!(app, AppState::Menu);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        crate::assert_state!(app, AppState::InGame);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected the app to be in state InGame, but it is in state Menu")]
+    fn test_assert_state_shows_actual_state() {
+        let mut app = create_app();
+        app.update();
+        crate::assert_state!(app, AppState::InGame);
+    }
+
+    #[test]
+    fn test_tab_cycles_focus_forward() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(app.world().resource::<MenuSelection>().0, 0);
+        press_key(&mut app, KeyCode::Tab);
+        app.update();
+        assert_eq!(app.world().resource::<MenuSelection>().0, 1);
+        assert_eq!(
+            get_menu_option_texts(&mut app),
+            vec!["\n  Start".to_string(), "\n> Quit".to_string()]
+        );
+        press_key(&mut app, KeyCode::Tab);
+        app.update();
+        assert_eq!(app.world().resource::<MenuSelection>().0, 0);
+    }
+
+    #[test]
+    fn test_shift_tab_cycles_focus_backward() {
+        let mut app = create_app();
+        app.update();
+        hold_key(&mut app, KeyCode::ShiftLeft);
+        app.update();
+        press_key(&mut app, KeyCode::Tab);
+        app.update();
+        assert_eq!(app.world().resource::<MenuSelection>().0, 1);
+        press_key(&mut app, KeyCode::Tab);
+        app.update();
+        assert_eq!(app.world().resource::<MenuSelection>().0, 0);
+        release_key(&mut app, KeyCode::ShiftLeft);
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_request_state() {
+        let mut app = create_app();
+        app.update();
+        request_state(&mut app, AppState::InGame);
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert_eq!(get_text(&mut app), text_for(Language::English, TextKey::Game));
+    }
+
+    #[test]
+    fn test_idle_game_returns_to_menu() {
+        let mut app = create_app_with_idle_timeout(1.0);
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert!(wait_for_state(&mut app, AppState::Menu, 20));
+    }
+
+    #[test]
+    fn test_input_resets_idle_timer() {
+        let mut app = create_app_with_idle_timeout(1.0);
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        for _ in 0..4 {
+            run_frames(&mut app, 5);
+            press_key(&mut app, KeyCode::KeyW);
+            app.update();
+        }
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_input_is_logged_with_state() {
+        let mut app = create_app();
+        app.insert_resource(LogInput(true));
+        app.init_resource::<InputLogs>();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        app.update();
+        assert_eq!(app.world().resource::<InputLogs>().0, vec!["[Menu] pressed Space"]);
+    }
+
+    #[test]
+    fn test_input_is_not_logged_by_default() {
+        let mut app = create_app();
+        app.init_resource::<InputLogs>();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        app.update();
+        assert!(app.world().resource::<InputLogs>().0.is_empty());
+    }
+
+    #[test]
+    fn test_pause_overlay_only_when_paused() {
+        let mut app = create_app_in_state(AppState::InGame);
+        app.update();
+        assert!(!has_pause_overlay(&mut app));
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert!(has_pause_overlay(&mut app));
+        press_key(&mut app, KeyCode::KeyP);
+        run_frames(&mut app, 2);
+        assert!(!has_pause_overlay(&mut app));
+    }
+
+    fn get_score_after_frames(difficulty: Difficulty, n_frames: usize) -> u32 {
+        let mut app = create_app_with_difficulty(difficulty);
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        run_frames(&mut app, n_frames);
+        get_score(&mut app)
+    }
+
+    #[test]
+    fn test_hard_difficulty_scores_faster_than_easy() {
+        let easy_score = get_score_after_frames(Difficulty::Easy, 20);
+        let hard_score = get_score_after_frames(Difficulty::Hard, 20);
+        assert!(hard_score > easy_score);
+    }
+
+    #[test]
+    fn test_menu_text_is_stable() {
+        let mut app = create_app();
+        app.update();
+        assert_text_stable_for(&mut app, "Menu. Press space to start", 10);
+    }
+
+    #[test]
+    fn test_credits_scroll_up() {
+        let mut app = create_app();
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::KeyC);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Credits);
+        let scroll_y_before = get_credits_scroll_y(&mut app);
+        run_frames(&mut app, 5);
+        assert!(get_credits_scroll_y(&mut app) > scroll_y_before);
+    }
+
+    #[test]
+    fn test_escape_leaves_credits() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::KeyC);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[derive(Resource, Default)]
+    struct StartedFromMenu(bool);
+
+    #[test]
+    fn test_on_transition_runs_only_at_that_transition() {
+        let mut app = create_app();
+        app.init_resource::<StartedFromMenu>();
+        on_transition(
+            &mut app,
+            AppState::Menu,
+            AppState::InGame,
+            |mut flag: ResMut<StartedFromMenu>| flag.0 = true,
+        );
+        app.update();
+        press_key(&mut app, KeyCode::KeyS);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Settings);
+        assert!(!app.world().resource::<StartedFromMenu>().0);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert!(!app.world().resource::<StartedFromMenu>().0);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert!(app.world().resource::<StartedFromMenu>().0);
+    }
+
+    #[test]
+    fn test_replayed_input_gives_same_state() {
+        let mut app = create_app();
+        app.init_resource::<InputRecording>();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        let recording = app.world().resource::<InputRecording>().clone();
+        assert_eq!(recording.0.len(), 6);
+        let path = std::env::temp_dir().join(format!(
+            "bevy_tdd_book_use_game_state_recording_{}.txt",
+            std::process::id()
+        ));
+        crate::persistence::save_input_recording(&path, &recording).unwrap();
+
+        let mut replay_app = create_app();
+        replay_app.update();
+        let loaded = crate::persistence::load_input_recording(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        replay_input(&mut replay_app, &loaded);
+        run_frames(&mut replay_app, 2);
+        assert_eq!(get_program_state(&mut replay_app), get_program_state(&mut app));
+        assert_eq!(get_program_state(&mut replay_app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_escape_takes_precedence_over_space_in_menu() {
+        let mut app = create_app();
+        app.update();
+        hold_key(&mut app, KeyCode::Space);
+        hold_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::ConfirmQuit);
+    }
+
+    #[test]
+    fn test_text_catalog_has_menu_text() {
+        let catalog = text_catalog();
+        assert_eq!(catalog.len(), all_app_states().len());
+        assert!(catalog.contains(&(AppState::Menu, "Menu. Press space to start".to_string())));
+    }
+
+    #[test]
+    fn test_menu_text_without_camera() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(count_cameras(&mut app), 0);
+        assert_eq!(count_n_texts(&mut app), 1);
+        assert_eq!(get_text(&mut app), "Menu. Press space to start");
+    }
+
+    #[test]
+    fn test_legal_transition_is_ok() {
+        assert_eq!(try_transition(AppState::Menu, AppState::InGame), Ok(AppState::InGame));
+        assert_eq!(try_transition(AppState::GameOver, AppState::InGame), Ok(AppState::InGame));
+    }
+
+    #[test]
+    fn test_illegal_transition_is_an_error() {
+        assert_eq!(
+            try_transition(AppState::Menu, AppState::GameOver),
+            Err(TransitionError {
+                from: AppState::Menu,
+                to: AppState::GameOver,
+            })
+        );
+    }
+
+    #[test]
+    fn test_all_used_transitions_are_allowed() {
+        for (from, to) in [
+            (AppState::Loading, AppState::Menu),
+            (AppState::Menu, AppState::Settings),
+            (AppState::Settings, AppState::Menu),
+            (AppState::Menu, AppState::ConfirmQuit),
+            (AppState::ConfirmQuit, AppState::Menu),
+            (AppState::InGame, AppState::Victory),
+            (AppState::Victory, AppState::Menu),
+        ] {
+            assert!(try_transition(from, to).is_ok(), "{:?} => {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn test_illegal_transition_is_not_requested() {
+        let state = State::new(AppState::Menu);
+        let mut next_state = NextState::<AppState>::Unchanged;
+        set_allowed_state(&state, &mut next_state, AppState::GameOver);
+        assert!(matches!(next_state, NextState::Unchanged));
+        set_allowed_state(&state, &mut next_state, AppState::InGame);
+        assert!(matches!(next_state, NextState::Pending(AppState::InGame)));
+    }
+
+    #[test]
+    fn test_camera_zooms_in_when_in_game() {
+        let mut app = create_app();
+        app.insert_resource(SpawnCamera(true));
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        run_frames(&mut app, 20);
+        let menu_scale = get_camera_scale(&mut app);
+        assert!((menu_scale - MENU_CAMERA_SCALE).abs() < 0.01);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        run_frames(&mut app, 3);
+        let scale = get_camera_scale(&mut app);
+        assert!(scale < menu_scale);
+        assert!(scale > IN_GAME_CAMERA_SCALE);
+        run_frames(&mut app, 20);
+        assert!((get_camera_scale(&mut app) - IN_GAME_CAMERA_SCALE).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_time_in_state() {
+        let mut app = create_app();
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        run_frames(&mut app, 10);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert!((time_in_state(&mut app, AppState::Menu) - 1.1).abs() < 0.01);
+        assert!((time_in_state(&mut app, AppState::InGame) - 0.1).abs() < 0.01);
+        assert_eq!(time_in_state(&mut app, AppState::Settings), 0.0);
+    }
+
+    #[test]
+    fn test_rebind_start_key_in_settings() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::KeyS);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Settings);
+        press_key(&mut app, KeyCode::Enter);
+        app.update();
+        assert!(app.world().resource::<RebindingStart>().0);
+        press_key(&mut app, KeyCode::KeyJ);
+        app.update();
+        assert!(!app.world().resource::<RebindingStart>().0);
+        assert_eq!(app.world().resource::<InputMap>().key(Action::Start), Some(KeyCode::KeyJ));
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        press_key(&mut app, KeyCode::KeyJ);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_escape_cancels_rebinding() {
+        let mut app = create_app_in_state(AppState::Settings);
+        app.update();
+        press_key(&mut app, KeyCode::Enter);
+        app.update();
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Settings);
+        assert_eq!(app.world().resource::<InputMap>().key(Action::Start), Some(KeyCode::Space));
+    }
+
+    #[test]
+    fn test_get_pending_state() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(get_pending_state(&mut app), None);
+        app.world_mut().resource_mut::<NextState<AppState>>().set(AppState::InGame);
+        assert_eq!(get_pending_state(&mut app), Some(AppState::InGame));
+        app.update();
+        assert_eq!(get_pending_state(&mut app), None);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    fn get_menu_text_visibility(app: &mut App) -> Visibility {
+        let mut query = app.world_mut().query_filtered::<&Visibility, With<MenuText>>();
+        *query.single(app.world())
+    }
+
+    #[test]
+    fn test_menu_text_does_not_blink_by_default() {
+        let mut app = create_app();
+        run_frames(&mut app, 10);
+        assert_ne!(get_menu_text_visibility(&mut app), Visibility::Hidden);
+    }
+
+    #[test]
+    fn test_menu_text_blinks() {
+        let mut app = create_app_with_blinking_prompt(0.5);
+        app.update();
+        assert_ne!(get_menu_text_visibility(&mut app), Visibility::Hidden);
+        run_frames(&mut app, 5);
+        assert_eq!(get_menu_text_visibility(&mut app), Visibility::Hidden);
+        run_frames(&mut app, 5);
+        assert_eq!(get_menu_text_visibility(&mut app), Visibility::Visible);
+    }
+
+    #[test]
+    fn test_menu_options_do_not_blink() {
+        let mut app = create_app_with_blinking_prompt(0.5);
+        app.update();
+        run_frames(&mut app, 5);
+        assert_eq!(get_menu_text_visibility(&mut app), Visibility::Hidden);
+        let mut query = app.world_mut().query_filtered::<&Parent, With<MenuOption>>();
+        let parents: Vec<Entity> = query.iter(app.world()).map(|parent| parent.get()).collect();
+        assert_eq!(parents.len(), MENU_OPTIONS.len());
+        for parent in parents {
+            let visibility = app.world().get::<Visibility>(parent).unwrap();
+            assert_ne!(*visibility, Visibility::Hidden);
+        }
+        assert_eq!(
+            get_menu_option_texts(&mut app),
+            vec!["\n> Start".to_string(), "\n  Quit".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_player_two_state_is_independent() {
+        let mut app = create_app();
+        app.update();
+        assert_eq!(get_player_two_state(&mut app), PlayerTwoState::Menu);
+        press_key(&mut app, KeyCode::Numpad1);
+        run_frames(&mut app, 2);
+        assert_eq!(get_player_two_state(&mut app), PlayerTwoState::InGame);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        press_key(&mut app, KeyCode::Numpad0);
+        run_frames(&mut app, 2);
+        assert_eq!(get_player_two_state(&mut app), PlayerTwoState::Menu);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_export_state_graph_dot() {
+        let dot = export_state_graph_dot();
+        assert!(dot.starts_with("digraph AppState {"));
+        assert!(dot.contains("Menu -> InGame [label=\"Space\"]"));
+        assert!(dot.contains("GameOver -> InGame [label=\"R\"]"));
+        assert!(!dot.contains("Loading -> InGame [label=\"Space\"]"));
+        assert!(dot.contains("Loading -> InGame [label=\"F1\", style=dashed]"));
+    }
+
+    #[test]
+    fn test_cannot_go_back_to_a_forbidden_state() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        app.world_mut().resource_mut::<Score>().0 = 100;
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Victory);
+        press_key(&mut app, KeyCode::Backspace);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Victory);
+        assert_eq!(app.world().resource::<StateHistory>().0.last(), Some(&AppState::InGame));
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_debug_shortcut_from_settings() {
+        let mut app = create_app_in_state(AppState::Settings);
+        app.insert_resource(DebugShortcuts(true));
+        app.update();
+        press_key(&mut app, KeyCode::F1);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_start_game_with_fixed_input() {
+        let mut app = create_app_with_fixed_input();
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_fixed_input_takes_each_key_press_once() {
+        let mut app = create_app_with_fixed_input();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        app.world_mut().run_schedule(PreUpdate);
+        assert_eq!(app.world().resource::<InputBuffer>().0, vec![KeyCode::Space]);
+        app.world_mut().run_schedule(FixedUpdate);
+        assert!(app.world().resource::<InputBuffer>().0.is_empty());
+        app.update();
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_ensure_states_plugin() {
+        let mut app = App::new();
+        assert!(!app.is_plugin_added::<bevy::state::app::StatesPlugin>());
+        ensure_states_plugin(&mut app);
+        assert!(app.is_plugin_added::<bevy::state::app::StatesPlugin>());
+        app.init_state::<AppState>();
+        app.update();
+        assert!(app.world().get_resource::<State<AppState>>().is_some());
+    }
+
+    #[test]
+    fn test_ensure_states_plugin_keeps_existing_plugin() {
+        let mut app = create_app();
+        ensure_states_plugin(&mut app);
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_transition_cooldown_ignores_quick_input() {
+        let mut app = create_app_with_transition_cooldown(0.15);
+        set_frame_time(&mut app, 0.1);
+        run_frames(&mut app, 3);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    fn click_left(app: &mut App) {
+        app.world_mut()
+            .send_event(bevy::input::mouse::MouseButtonInput {
+                button: MouseButton::Left,
+                state: bevy::input::ButtonState::Pressed,
+                window: Entity::PLACEHOLDER,
+            });
+    }
+
+    #[test]
+    fn test_transition_cooldown_ignores_quick_clicks() {
+        let mut app = create_app_with_transition_cooldown(0.15);
+        set_frame_time(&mut app, 0.1);
+        run_frames(&mut app, 3);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 4);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        click_left(&mut app);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        click_left(&mut app);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_transition_cooldown_ignores_quick_escape_in_countdown() {
+        let mut app = create_app_with_countdown(3.0);
+        app.insert_resource(TransitionCooldown::new(0.15));
+        set_frame_time(&mut app, 0.1);
+        run_frames(&mut app, 3);
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Countdown);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Countdown);
+    }
+
+    #[test]
+    fn test_no_transition_cooldown_by_default() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::Space);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Escape);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[derive(Component)]
+    struct TestMarker;
+
+    #[test]
+    fn test_despawn_tagged() {
+        use bevy::ecs::system::RunSystemOnce;
+        let mut app = create_app();
+        app.update();
+        let child = app.world_mut().spawn(Transform::default()).id();
+        app.world_mut().spawn(TestMarker).add_child(child);
+        app.world_mut().spawn(TestMarker);
+        let untagged = app.world_mut().spawn(Transform::default()).id();
+        app.world_mut().run_system_once(despawn_tagged::<TestMarker>).unwrap();
+        let mut query = app.world_mut().query::<&TestMarker>();
+        assert_eq!(query.iter(app.world()).count(), 0);
+        assert!(!app.world().entities().contains(child));
+        assert!(app.world().entities().contains(untagged));
+    }
+
+    #[test]
+    fn test_tap_starts_game() {
+        let mut app = create_app();
+        app.update();
+        app.world_mut().send_event(bevy::input::touch::TouchInput {
+            phase: bevy::input::touch::TouchPhase::Started,
+            position: Vec2::new(100.0, 100.0),
+            window: Entity::PLACEHOLDER,
+            force: None,
+            id: 0,
+        });
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    fn get_camera_clear_color_name(app: &mut App) -> &'static str {
+        let mut query = app.world_mut().query::<&Camera>();
+        match query.single(app.world()).clear_color {
+            ClearColorConfig::Default => "Default",
+            ClearColorConfig::Custom(_) => "Custom",
+            ClearColorConfig::None => "None",
+        }
+    }
+
+    #[test]
+    fn test_reset_camera() {
+        let mut app = create_app();
+        app.insert_resource(SpawnCamera(true));
+        app.update();
+        assert_eq!(get_camera_clear_color_name(&mut app), "Default");
+        app.insert_resource(CameraConfig {
+            clear: true,
+            color: Some(Color::WHITE),
+        });
+        app.update();
+        assert_eq!(get_camera_clear_color_name(&mut app), "Custom");
+        app.insert_resource(CameraConfig {
+            clear: false,
+            color: None,
+        });
+        app.update();
+        assert_eq!(get_camera_clear_color_name(&mut app), "None");
+        reset_camera(&mut app);
+        app.update();
+        assert_eq!(*app.world().resource::<CameraConfig>(), CameraConfig::default());
+        assert_eq!(get_camera_clear_color_name(&mut app), "Default");
+    }
+
+    fn send_key_with_logical_key(
+        app: &mut App,
+        key: KeyCode,
+        logical_key: bevy::input::keyboard::Key,
+    ) {
+        for state in [bevy::input::ButtonState::Pressed, bevy::input::ButtonState::Released] {
+            app.world_mut().send_event(bevy::input::keyboard::KeyboardInput {
+                key_code: key,
+                logical_key: logical_key.clone(),
+                state,
+                window: Entity::PLACEHOLDER,
+                repeat: false,
+            });
+        }
+    }
+
+    #[test]
+    fn test_logical_space_starts_game() {
+        let mut app = create_app();
+        app.update();
+        send_key_with_logical_key(&mut app, KeyCode::KeyQ, bevy::input::keyboard::Key::Space);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_other_logical_key_does_not_start_game() {
+        let mut app = create_app();
+        app.update();
+        send_key_with_logical_key(
+            &mut app,
+            KeyCode::KeyQ,
+            bevy::input::keyboard::Key::Character("a".into()),
+        );
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_custom_key_bindings() {
+        let mut app = create_app_with_bindings(KeyBindings {
+            start: KeyCode::KeyG,
+            quit: KeyCode::KeyX,
+        });
+        app.update();
+        assert_eq!(get_key_bindings(&mut app).start, KeyCode::KeyG);
+        press_key(&mut app, KeyCode::KeyG);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        press_key(&mut app, KeyCode::KeyX);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+    }
+
+    #[test]
+    fn test_enter_needs_a_long_hold_in_hold_to_start_mode() {
+        let mut app = create_app();
+        app.insert_resource(HoldToStart {
+            timer: Timer::from_seconds(1.0, TimerMode::Once),
+            enabled: true,
+        });
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        press_key(&mut app, KeyCode::Enter);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        hold_key(&mut app, KeyCode::Enter);
+        run_frames(&mut app, 15);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_enter_starts_game_with_start_highlighted_again() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::ArrowDown);
+        app.update();
+        press_key(&mut app, KeyCode::ArrowUp);
+        app.update();
+        press_key(&mut app, KeyCode::Enter);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_request_current_state_does_nothing() {
+        let mut app = create_app();
+        app.update();
+        request_state(&mut app, AppState::Menu);
+        assert_eq!(get_pending_state(&mut app), None);
+        request_state(&mut app, AppState::InGame);
+        assert_eq!(get_pending_state(&mut app), Some(AppState::InGame));
+    }
+
+    #[test]
+    fn test_debug_shortcut_in_game_does_not_fade() {
+        let mut app = create_app_with_fade(0.5);
+        app.insert_resource(DebugShortcuts(true));
+        set_frame_time(&mut app, 0.1);
+        app.update();
+        request_state(&mut app, AppState::InGame);
+        run_frames(&mut app, 20);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+        assert_eq!(count_fade_overlays(&mut app), 0);
+        press_key(&mut app, KeyCode::F1);
+        app.update();
+        assert_eq!(count_fade_overlays(&mut app), 0);
+    }
+
+    #[test]
+    fn test_backspace_while_rebinding_is_refused() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::KeyS);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Enter);
+        app.update();
+        press_key(&mut app, KeyCode::Backspace);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Settings);
+        assert!(app.world().resource::<RebindingStart>().0);
+        assert_eq!(app.world().resource::<InputMap>().key(Action::Start), Some(KeyCode::Space));
+        press_key(&mut app, KeyCode::KeyJ);
+        app.update();
+        assert!(!app.world().resource::<RebindingStart>().0);
+        assert_eq!(app.world().resource::<InputMap>().key(Action::Start), Some(KeyCode::KeyJ));
+    }
+
+    #[test]
+    fn test_keys_in_use_cannot_become_the_start_key() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::KeyS);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Enter);
+        app.update();
+        for key in [KeyCode::Enter, KeyCode::KeyP, KeyCode::ArrowUp] {
+            press_key(&mut app, key);
+            app.update();
+            assert!(app.world().resource::<RebindingStart>().0);
+        }
+        assert_eq!(app.world().resource::<InputMap>().key(Action::Start), Some(KeyCode::Space));
+    }
+
+
+    #[test]
+    fn test_player_two_keys_in_menu_are_not_reported() {
+        let mut app = create_app();
+        app.update();
+        for key in [KeyCode::Numpad1, KeyCode::Numpad0] {
+            press_key(&mut app, key);
+            app.update();
+            let events = app.world().resource::<Events<InvalidKeyPressed>>();
+            assert_eq!(events.iter_current_update_events().count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_f1_in_menu_is_only_reported_without_debug_shortcuts() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::F1);
+        app.update();
+        let events = app.world().resource::<Events<InvalidKeyPressed>>();
+        assert_eq!(events.iter_current_update_events().count(), 1);
+
+        let mut app = create_app();
+        app.insert_resource(DebugShortcuts(true));
+        app.update();
+        press_key(&mut app, KeyCode::F1);
+        app.update();
+        let events = app.world().resource::<Events<InvalidKeyPressed>>();
+        let expected = if cfg!(debug_assertions) { 0 } else { 1 };
+        assert_eq!(events.iter_current_update_events().count(), expected);
+    }
+
+    #[test]
+    fn test_unidentified_logical_keys_do_not_start_game() {
+        let input_map = InputMap::default().with(Action::Start, KeyCode::F2);
+        let mut app = create_app_with_input_map(input_map);
+        app.update();
+        press_key(&mut app, KeyCode::F3);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        press_key(&mut app, KeyCode::F2);
+        run_frames(&mut app, 2);
+        assert_eq!(get_program_state(&mut app), AppState::InGame);
+    }
+
+    #[test]
+    fn test_leaving_settings_while_rebinding_stops_rebinding() {
+        let mut app = create_app();
+        app.update();
+        press_key(&mut app, KeyCode::KeyS);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Enter);
+        app.update();
+        assert!(app.world().resource::<RebindingStart>().0);
+        request_state(&mut app, AppState::Menu);
+        app.update();
+        assert_eq!(get_program_state(&mut app), AppState::Menu);
+        assert!(!app.world().resource::<RebindingStart>().0);
+        press_key(&mut app, KeyCode::KeyS);
+        run_frames(&mut app, 2);
+        press_key(&mut app, KeyCode::Backspace);
+        run_frames(&mut app, 2);
         assert_eq!(get_program_state(&mut app), AppState::Menu);
     }
 
@@ -1,13 +1,6 @@
-use crate::app::*;
-mod app;
+use bevy_tdd_book_use_game_state::app::*;
 
 fn main() {
-    let mut app = create_app();
-    //let add_camera_fn = |mut commands: Commands| {
-    //    commands.spawn(Camera2d);
-    //};
-    //app.add_systems(Startup, add_camera_fn);
-    //app.add_plugins(DefaultPlugins);
-
+    let mut app = create_windowed_app();
     app.run();
 }
@@ -0,0 +1,136 @@
+//! Saving and loading the high score and recorded input to small plain-text files.
+
+use std::path::Path;
+
+use bevy::input::ButtonState;
+use bevy::prelude::KeyCode;
+use bevy::reflect::{DynamicEnum, DynamicVariant, FromReflect};
+
+use crate::app::InputRecording;
+
+/// Save the high score to the file, unless the file has a higher score already
+pub fn save_high_score(path: &Path, score: u32) -> std::io::Result<()> {
+    let high_score = load_high_score(path).max(score);
+    std::fs::write(path, high_score.to_string())
+}
+
+/// Load the high score from the file, which is zero if there is no valid file
+pub fn load_high_score(path: &Path) -> u32 {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| text.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Save the recorded input to the file, as one 'frame key state' line per event.
+/// The key is saved by its name, such as 'KeyA' or 'Numpad2'
+pub fn save_input_recording(path: &Path, recording: &InputRecording) -> std::io::Result<()> {
+    let text: String = recording
+        .0
+        .iter()
+        .map(|(frame, key, state)| format!("{} {:?} {:?}\n", frame, key, state))
+        .collect();
+    std::fs::write(path, text)
+}
+
+/// Load the recorded input from the file.
+/// A line that cannot be read is an error, so a replay never silently misses a key
+pub fn load_input_recording(path: &Path) -> std::io::Result<InputRecording> {
+    let text = std::fs::read_to_string(path)?;
+    let events = text
+        .lines()
+        .map(|line| {
+            parse_recorded_event(line).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("cannot read recorded input '{}'", line),
+                )
+            })
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    Ok(InputRecording(events))
+}
+
+fn parse_recorded_event(line: &str) -> Option<(u64, KeyCode, ButtonState)> {
+    let mut words = line.split_whitespace();
+    let frame = words.next()?.parse().ok()?;
+    let key = parse_key_code(words.next()?)?;
+    let state = match words.next()? {
+        "Pressed" => ButtonState::Pressed,
+        "Released" => ButtonState::Released,
+        _ => return None,
+    };
+    Some((frame, key, state))
+}
+
+/// Get the key from its name, which works for every key but 'KeyCode::Unidentified'
+fn parse_key_code(name: &str) -> Option<KeyCode> {
+    KeyCode::from_reflect(&DynamicEnum::new(name.to_string(), DynamicVariant::Unit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "bevy_tdd_book_use_game_state_{}_{}.txt",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_missing_file_has_zero_high_score() {
+        let path = create_temp_path("missing");
+        assert_eq!(load_high_score(&path), 0);
+    }
+
+    #[test]
+    fn test_save_and_load_high_score() {
+        let path = create_temp_path("save_and_load");
+        save_high_score(&path, 42).unwrap();
+        assert_eq!(load_high_score(&path), 42);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_smaller_score_does_not_overwrite_high_score() {
+        let path = create_temp_path("smaller");
+        save_high_score(&path, 42).unwrap();
+        save_high_score(&path, 7).unwrap();
+        assert_eq!(load_high_score(&path), 42);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_recording_is_an_error() {
+        let path = create_temp_path("missing_recording");
+        assert!(load_input_recording(&path).is_err());
+    }
+
+    #[test]
+    fn test_save_and_load_input_recording() {
+        let path = create_temp_path("recording");
+        let recording = InputRecording(vec![
+            (3, KeyCode::Space, ButtonState::Pressed),
+            (4, KeyCode::Space, ButtonState::Released),
+            (9, KeyCode::Escape, ButtonState::Pressed),
+            (12, KeyCode::Numpad2, ButtonState::Pressed),
+            (13, KeyCode::ControlLeft, ButtonState::Pressed),
+        ]);
+        save_input_recording(&path, &recording).unwrap();
+        assert_eq!(load_input_recording(&path).unwrap().0, recording.0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unreadable_recording_is_an_error() {
+        let path = create_temp_path("unreadable_recording");
+        std::fs::write(&path, "3 Space Pressed\n4 NoSuchKey Pressed\n").unwrap();
+        assert!(load_input_recording(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,31 @@
+use bevy::prelude::KeyCode;
+use bevy_tdd_book_use_game_state::app::*;
+use bevy_tdd_book_use_game_state::assert_state;
+
+#[test]
+fn test_app_starts_at_menu() {
+    let mut app = create_app();
+    app.update();
+    assert_eq!(get_app_state(&mut app), AppState::Menu);
+}
+
+#[test]
+fn test_start_and_quit_game() {
+    let mut app = create_app();
+    app.update();
+    press_key(&mut app, KeyCode::Space);
+    run_frames(&mut app, 2);
+    assert_eq!(get_app_state(&mut app), AppState::InGame);
+    press_key(&mut app, KeyCode::Escape);
+    run_frames(&mut app, 2);
+    assert_eq!(get_app_state(&mut app), AppState::Menu);
+}
+
+#[test]
+fn test_enter_starts_game() {
+    let mut app = create_app();
+    app.update();
+    press_key(&mut app, KeyCode::Enter);
+    run_frames(&mut app, 2);
+    assert_state!(app, AppState::InGame);
+}
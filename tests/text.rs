@@ -0,0 +1,9 @@
+use bevy_tdd_book_use_game_state::app::*;
+
+#[test]
+fn test_app_has_menu_text() {
+    let mut app = create_app();
+    app.update();
+    assert_eq!(count_n_texts(&mut app), 1);
+    assert_eq!(get_text(&mut app), "Menu. Press space to start");
+}